@@ -0,0 +1,230 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+//! Run a saver in a window, acting as its locker.
+//!
+//! Usage: `screenruster-preview SAVER [CONFIG]`
+//!
+//! Mouse events are forwarded as pointer events, any printable key inserts
+//! into the password, Backspace deletes, Return checks and Escape stops the
+//! saver.
+
+use std::env;
+use std::fs;
+use std::ptr;
+use std::mem;
+use std::thread;
+use std::process;
+use std::ffi::CString;
+use std::time::Duration;
+
+use x11::{xlib, keysym};
+use screenruster_saver::{json, SaverProcess, Request, Password, Pointer};
+use screenruster_saver::{error, info};
+
+const WIDTH:  u32 = 1280;
+const HEIGHT: u32 = 720;
+
+fn main() {
+	env_logger::init();
+
+	let mut args = env::args().skip(1);
+	let saver    = if let Some(saver) = args.next() {
+		saver
+	}
+	else {
+		eprintln!("usage: screenruster-preview SAVER [CONFIG]");
+		process::exit(1);
+	};
+
+	let config = if let Some(path) = args.next() {
+		match fs::read_to_string(&path).map(|c| json::parse(&c)) {
+			Ok(Ok(config)) => {
+				config
+			}
+
+			Ok(Err(err)) => {
+				error!("{}: {}", path, err);
+				process::exit(1);
+			}
+
+			Err(err) => {
+				error!("{}: {}", path, err);
+				process::exit(1);
+			}
+		}
+	}
+	else {
+		json::object!{}
+	};
+
+	unsafe {
+		let display = xlib::XOpenDisplay(ptr::null());
+		if display.is_null() {
+			error!("could not open display");
+			process::exit(1);
+		}
+
+		let screen = xlib::XDefaultScreen(display);
+		let root   = xlib::XRootWindow(display, screen);
+		let window = xlib::XCreateSimpleWindow(display, root, 0, 0, WIDTH, HEIGHT, 0,
+			xlib::XBlackPixel(display, screen), xlib::XBlackPixel(display, screen));
+
+		let title = CString::new(format!("screenruster-preview: {}", saver)).unwrap();
+		xlib::XStoreName(display, window, title.as_ptr());
+
+		let name       = CString::new("WM_DELETE_WINDOW").unwrap();
+		let mut delete = xlib::XInternAtom(display, name.as_ptr(), xlib::False);
+		xlib::XSetWMProtocols(display, window, &mut delete, 1);
+
+		xlib::XSelectInput(display, window, xlib::ExposureMask | xlib::StructureNotifyMask |
+			xlib::KeyPressMask | xlib::ButtonPressMask | xlib::ButtonReleaseMask | xlib::PointerMotionMask);
+
+		xlib::XMapWindow(display, window);
+		xlib::XSync(display, xlib::False);
+
+		let mut process = match SaverProcess::spawn(&saver) {
+			Ok(process) => {
+				process
+			}
+
+			Err(err) => {
+				error!("{}: {}", saver, err);
+				process::exit(1);
+			}
+		};
+
+		let started = process.config(config)
+			.and_then(|_| process.target(env::var("DISPLAY").ok(), screen, window))
+			.and_then(|_| process.start());
+
+		if let Err(err) = started {
+			error!("{}: {}", saver, err);
+			eprint!("{}", process.stderr());
+			process::exit(1);
+		}
+
+		info!("{} started", saver);
+
+		let mut size  = (WIDTH, HEIGHT);
+		let mut event = mem::zeroed::<xlib::XEvent>();
+
+		'main: loop {
+			while xlib::XPending(display) > 0 {
+				xlib::XNextEvent(display, &mut event);
+
+				let request = match event.get_type() {
+					xlib::MotionNotify => {
+						let event = xlib::XMotionEvent::from(event);
+
+						Some(Request::Pointer(Pointer::Move {
+							x: event.x,
+							y: event.y,
+						}))
+					}
+
+					xlib::ButtonPress | xlib::ButtonRelease => {
+						let event = xlib::XButtonEvent::from(event);
+
+						Some(Request::Pointer(Pointer::Button {
+							x: event.x,
+							y: event.y,
+
+							button: event.button as u8,
+							press:  event.type_ == xlib::ButtonPress,
+						}))
+					}
+
+					xlib::KeyPress => {
+						let mut key = xlib::XKeyEvent::from(event);
+
+						match xlib::XLookupKeysym(&mut key, 0) as u32 {
+							keysym::XK_Escape =>
+								break 'main,
+
+							keysym::XK_BackSpace =>
+								Some(Request::Password(Password::Delete)),
+
+							keysym::XK_Return | keysym::XK_KP_Enter =>
+								Some(Request::Password(Password::Check)),
+
+							sym if (0x20 ..= 0x7e).contains(&sym) =>
+								Some(Request::Password(Password::Insert)),
+
+							_ =>
+								None
+						}
+					}
+
+					xlib::ConfigureNotify => {
+						let event = xlib::XConfigureEvent::from(event);
+						let new   = (event.width as u32, event.height as u32);
+
+						if new != size {
+							size = new;
+
+							Some(Request::Resize {
+								width:  new.0,
+								height: new.1,
+							})
+						}
+						else {
+							None
+						}
+					}
+
+					xlib::ClientMessage => {
+						let event = xlib::XClientMessageEvent::from(event);
+
+						if event.data.get_long(0) as xlib::Atom == delete {
+							break 'main;
+						}
+
+						None
+					}
+
+					_ =>
+						None
+				};
+
+				if let Some(request) = request {
+					if let Err(err) = process.send(request) {
+						error!("{}: {}", saver, err);
+						break 'main;
+					}
+				}
+			}
+
+			if let Ok(Some(status)) = process.try_wait() {
+				error!("{} exited early: {}", saver, status);
+				break 'main;
+			}
+
+			thread::sleep(Duration::from_millis(10));
+		}
+
+		match process.stop() {
+			Ok(status) =>
+				info!("{} stopped: {}", saver, status),
+
+			Err(err) =>
+				error!("{}: {}", saver, err),
+		}
+
+		eprint!("{}", process.stderr());
+
+		xlib::XDestroyWindow(display, window);
+		xlib::XCloseDisplay(display);
+	}
+}
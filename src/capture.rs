@@ -0,0 +1,177 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+//! Golden image comparison for captured frames.
+
+use std::path::{Path, PathBuf};
+
+use picto;
+
+use crate::error;
+
+/// A captured frame.
+pub type Frame = picto::buffer::Rgba;
+
+/// Compare the frame against the reference image at the given path.
+///
+/// Pixels with any channel differing by more than `tolerance` are mismatches,
+/// in which case a diff image is written next to the reference, with the
+/// mismatches in red over a faded copy of the frame.
+pub fn compare<P: AsRef<Path>>(frame: &Frame, reference: P, tolerance: u8) -> error::Result<()> {
+	let reference = reference.as_ref();
+	let expected  = picto::read::from_path::<picto::color::Rgba, u8, _>(reference)?;
+	let diff      = path(reference);
+
+	// Nothing to compare pixel by pixel, keep the frame around to look at.
+	if expected.dimensions() != frame.dimensions() {
+		picto::write::to_path(&diff, frame)?;
+
+		return Err(error::Error::Mismatch {
+			pixels: (frame.width() * frame.height()) as usize,
+			diff:   diff,
+		});
+	}
+
+	let (width, height) = frame.dimensions();
+	let expected        = expected.into_raw();
+	let actual          = frame.clone().into_raw();
+
+	let mut pixels = 0;
+	let mut output = Vec::with_capacity(actual.len());
+
+	for (a, b) in actual.chunks(4).zip(expected.chunks(4)) {
+		if a.iter().zip(b).any(|(&a, &b)| (a as i16 - b as i16).abs() > tolerance as i16) {
+			pixels += 1;
+			output.extend_from_slice(&[0xff, 0x00, 0x00, 0xff]);
+		}
+		else {
+			output.extend_from_slice(&[a[0] / 4, a[1] / 4, a[2] / 4, 0xff]);
+		}
+	}
+
+	if pixels == 0 {
+		return Ok(());
+	}
+
+	picto::write::to_path(&diff, &Frame::from_raw(width, height, output).unwrap())?;
+
+	Err(error::Error::Mismatch {
+		pixels: pixels,
+		diff:   diff,
+	})
+}
+
+/// The path of the diff image for the given reference.
+fn path(reference: &Path) -> PathBuf {
+	let mut name = reference.file_stem().unwrap_or_default().to_os_string();
+	name.push(".diff.png");
+
+	reference.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env;
+	use std::fs;
+	use std::process;
+	use super::*;
+
+	/// A scratch directory of its own for each test.
+	fn scratch(name: &str) -> PathBuf {
+		let path = env::temp_dir().join(format!("screenruster-capture-{}-{}", process::id(), name));
+		fs::create_dir_all(&path).unwrap();
+
+		path
+	}
+
+	fn frame(width: u32, height: u32, pixel: [u8; 4]) -> Frame {
+		let data = pixel.iter().cloned().cycle().take((width * height * 4) as usize).collect();
+		Frame::from_raw(width, height, data).unwrap()
+	}
+
+	#[test]
+	fn identical() {
+		let dir       = scratch("identical");
+		let reference = dir.join("frame.png");
+		let image     = frame(4, 4, [10, 20, 30, 255]);
+
+		picto::write::to_path(&reference, &image).unwrap();
+		compare(&image, &reference, 0).unwrap();
+		assert!(!dir.join("frame.diff.png").exists());
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn tolerance() {
+		let dir       = scratch("tolerance");
+		let reference = dir.join("frame.png");
+
+		picto::write::to_path(&reference, &frame(4, 4, [10, 20, 30, 255])).unwrap();
+		compare(&frame(4, 4, [13, 17, 30, 255]), &reference, 3).unwrap();
+		assert!(!dir.join("frame.diff.png").exists());
+
+		// One past the tolerance.
+		assert!(compare(&frame(4, 4, [14, 20, 30, 255]), &reference, 3).is_err());
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn mismatch() {
+		let dir       = scratch("mismatch");
+		let reference = dir.join("frame.png");
+		let mut image = frame(4, 4, [10, 20, 30, 255]);
+
+		picto::write::to_path(&reference, &image).unwrap();
+		image.set(1, 2, &picto::color::Rgba::new(1.0, 1.0, 1.0, 1.0));
+		image.set(3, 0, &picto::color::Rgba::new(0.0, 0.0, 0.0, 1.0));
+
+		match compare(&image, &reference, 4) {
+			Err(error::Error::Mismatch { pixels, diff }) => {
+				assert_eq!(pixels, 2);
+				assert_eq!(diff, dir.join("frame.diff.png"));
+
+				let diff = picto::read::from_path::<picto::color::Rgba, u8, _>(&diff).unwrap();
+				assert_eq!(diff.dimensions(), (4, 4));
+				assert_eq!(diff.get(1, 2), picto::color::Rgba::new(1.0, 0.0, 0.0, 1.0));
+			}
+
+			result =>
+				panic!("unexpected result: {:?}", result),
+		}
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn dimensions() {
+		let dir       = scratch("dimensions");
+		let reference = dir.join("frame.png");
+
+		picto::write::to_path(&reference, &frame(4, 4, [10, 20, 30, 255])).unwrap();
+
+		match compare(&frame(2, 3, [10, 20, 30, 255]), &reference, 255) {
+			Err(error::Error::Mismatch { pixels, diff }) => {
+				assert_eq!(pixels, 6);
+				assert_eq!(picto::read::from_path::<picto::color::Rgba, u8, _>(&diff).unwrap().dimensions(), (2, 3));
+			}
+
+			result =>
+				panic!("unexpected result: {:?}", result),
+		}
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
@@ -12,23 +12,47 @@
 //
 //  0. You just DO WHAT THE FUCK YOU WANT TO.
 
-use std::io::{Read, BufRead, BufReader, Write};
+use std::io::{self, Read, BufRead, BufReader, Write};
 use std::thread;
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use json::{self, JsonValue, object};
-use crossbeam_channel::{unbounded, Receiver, RecvError, Sender, SendError};
+use log::warn;
+use crossbeam_channel::{select, bounded, unbounded, Receiver, RecvError, Sender, SendError};
 
 use crate::error;
-use crate::{Safety, Password, Pointer};
+use crate::{Safety, Password, Pointer, Stats};
+
+/// The protocol version spoken by this crate.
+pub const VERSION: u32 = 1;
+
+/// The oldest protocol version still understood.
+pub const MINIMUM: u32 = 1;
+
+/// The optional protocol features supported by this crate.
+pub const CAPABILITIES: &[&str] = &["heartbeat", "ping", "stats"];
 
 /// Communication between locker and saver.
 pub struct Channel {
 	receiver: Receiver<Request>,
 	sender:   Sender<Response>,
+	flusher:  Sender<Sender<()>>,
+	failure:  Arc<Mutex<Option<error::Protocol>>>,
+
+	version:      u32,
+	capabilities: Vec<String>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Request {
+	/// Protocol handshake, must be the first message.
+	Hello {
+		version:      u32,
+		capabilities: Vec<String>,
+	},
+
 	/// Saver configuration.
 	Config(JsonValue),
 
@@ -68,10 +92,20 @@ pub enum Request {
 
 	/// Stop the saver.
 	Stop,
+
+	/// Check the saver is still rendering, answered with a `Pong` carrying the
+	/// same id.
+	Ping(u64),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Response {
+	/// The negotiated protocol version and capabilities.
+	Hello {
+		version:      u32,
+		capabilities: Vec<String>,
+	},
+
 	/// The saver has been initialized.
 	Initialized,
 
@@ -80,153 +114,581 @@ pub enum Response {
 
 	/// The saver has stopped.
 	Stopped,
+
+	/// The saver is alive and has rendered the given number of frames.
+	Heartbeat {
+		frame: u64,
+	},
+
+	/// Answer to a `Ping` from the render thread.
+	Pong(u64),
+
+	/// Periodic rendering statistics.
+	Stats(Stats),
+
+	/// Something went wrong in the saver.
+	Error {
+		kind:    String,
+		message: String,
+		fatal:   bool,
+	},
 }
 
-impl Channel {
-	/// Open the channel on the given input and output streams.
-	pub fn open<R: Read + Send + 'static, W: Write + Send + 'static>(input: R, output: W) -> error::Result<Channel> {
-		let (sender, i_receiver) = unbounded();
-		let (i_sender, receiver) = unbounded();
+/// Channel settings.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Options {
+	/// Report malformed messages back to the locker.
+	pub report: bool,
 
-		// Reader.
-		thread::spawn(move || {
-			macro_rules! json {
-				($body:expr) => (
-					if let Some(value) = $body {
-						value
-					}
-					else {
-						continue;
-					}
-				);
+	/// Treat malformed messages as fatal and close the channel.
+	pub strict: bool,
+}
+
+impl Request {
+	/// Decode a request from its JSON representation.
+	pub fn from_json(message: &JsonValue) -> Result<Request, error::Protocol> {
+		Ok(match field(message, "type", "string", JsonValue::as_str)? {
+			"hello" => {
+				Request::Hello {
+					version:      field(message, "version", "integer", JsonValue::as_u32)?,
+					capabilities: message["capabilities"].members()
+						.filter_map(|c| c.as_str()).map(Into::into).collect(),
+				}
+			}
+
+			"config" => {
+				Request::Config(message["config"].clone())
 			}
 
-			for line in BufReader::new(input).lines() {
-				if line.is_err() {
-					break;
+			"target" => {
+				Request::Target {
+					display: message["display"].as_str().map(Into::into),
+					screen:  field(message, "screen", "integer", JsonValue::as_i32)?,
+					window:  field(message, "window", "integer", JsonValue::as_u64)?,
 				}
+			}
 
-				if let Ok(message) = json::parse(&line.unwrap()) {
-					sender.send(match json!(message["type"].as_str()) {
-						"config" => {
-							Request::Config(message["config"].clone())
-						}
+			"resize" => {
+				Request::Resize {
+					width:  field(message, "width", "integer", JsonValue::as_u32)?,
+					height: field(message, "height", "integer", JsonValue::as_u32)?,
+				}
+			}
 
-						"target" => {
-							Request::Target {
-								display: message["display"].as_str().map(Into::into),
-								screen:  json!(message["screen"].as_i32()),
-								window:  json!(message["window"].as_u64()),
-							}
-						}
+			"throttle" => {
+				Request::Throttle(field(message, "throttle", "boolean", JsonValue::as_bool)?)
+			}
 
-						"resize" => {
-							Request::Resize {
-								width:  json!(message["width"].as_u32()),
-								height: json!(message["height"].as_u32()),
-							}
-						}
+			"blank" => {
+				Request::Blank(field(message, "blank", "boolean", JsonValue::as_bool)?)
+			}
 
-						"throttle" => {
-							Request::Throttle(json!(message["throttle"].as_bool()))
-						}
+			"safety" => {
+				Request::Safety(match field(message, "safety", "string", JsonValue::as_str)? {
+					"high"   => Safety::High,
+					"medium" => Safety::Medium,
+					"low"    => Safety::Low,
 
-						"blank" => {
-							Request::Blank(json!(message["blank"].as_bool()))
-						}
+					value =>
+						return Err(error::Protocol::InvalidValue { field: "safety".into(), value: value.into() })
+				})
+			}
 
-						"safety" => {
-							Request::Safety(match json!(message["safety"].as_str()) {
-								"high"   => Safety::High,
-								"medium" => Safety::Medium,
-								"low"    => Safety::Low,
+			"pointer" => {
+				if !message["move"].is_null() {
+					Request::Pointer(Pointer::Move {
+						x: field(message, "move.x", "integer", JsonValue::as_i32)?,
+						y: field(message, "move.y", "integer", JsonValue::as_i32)?,
+					})
+				}
+				else if !message["button"].is_null() {
+					Request::Pointer(Pointer::Button {
+						x: field(message, "button.x", "integer", JsonValue::as_i32)?,
+						y: field(message, "button.y", "integer", JsonValue::as_i32)?,
+
+						button: field(message, "button.button", "integer", JsonValue::as_u8)?,
+						press:  field(message, "button.press", "boolean", JsonValue::as_bool)?,
+					})
+				}
+				else {
+					return Err(error::Protocol::MissingField("move".into()));
+				}
+			}
 
-								_ =>
-									continue
-							})
-						}
+			"password" => {
+				Request::Password(match field(message, "password", "string", JsonValue::as_str)? {
+					"insert"  => Password::Insert,
+					"delete"  => Password::Delete,
+					"reset"   => Password::Reset,
+					"check"   => Password::Check,
+					"success" => Password::Success,
+					"failure" => Password::Failure,
+
+					value =>
+						return Err(error::Protocol::InvalidValue { field: "password".into(), value: value.into() })
+				})
+			}
 
-						"pointer" => {
-							if !message["move"].is_null() {
-								Request::Pointer(Pointer::Move {
-									x: json!(message["move"]["x"].as_i32()),
-									y: json!(message["move"]["y"].as_i32()),
-								})
-							}
-							else if !message["button"].is_null() {
-								Request::Pointer(Pointer::Button {
-									x: json!(message["button"]["x"].as_i32()),
-									y: json!(message["button"]["y"].as_i32()),
-
-									button: json!(message["button"]["button"].as_u8()),
-									press:  json!(message["button"]["press"].as_bool()),
-								})
-							}
-							else {
-								continue;
-							}
-						}
+			"start" => {
+				Request::Start
+			}
 
-						"password" => {
-							Request::Password(match json!(message["password"].as_str()) {
-								"insert"  => Password::Insert,
-								"delete"  => Password::Delete,
-								"reset"   => Password::Reset,
-								"check"   => Password::Check,
-								"success" => Password::Success,
-								"failure" => Password::Failure,
-
-								_ =>
-									continue
-							})
-						}
+			"lock" => {
+				Request::Lock
+			}
 
-						"start" => {
-							Request::Start
-						}
+			"stop" => {
+				Request::Stop
+			}
 
-						"lock" => {
-							Request::Lock
-						}
+			"ping" => {
+				Request::Ping(field(message, "id", "integer", JsonValue::as_u64)?)
+			}
+
+			kind =>
+				return Err(error::Protocol::UnknownType(kind.into()))
+		})
+	}
+
+	/// Encode the request to its JSON representation.
+	pub fn to_json(&self) -> JsonValue {
+		match *self {
+			Request::Hello { version, ref capabilities } => object!{
+				"type"         => "hello",
+				"version"      => version,
+				"capabilities" => capabilities.clone()
+			},
+
+			Request::Config(ref config) => object!{
+				"type"   => "config",
+				"config" => config.clone()
+			},
+
+			Request::Target { ref display, screen, window } => object!{
+				"type"    => "target",
+				"display" => display.clone(),
+				"screen"  => screen,
+				"window"  => window
+			},
+
+			Request::Resize { width, height } => object!{
+				"type"   => "resize",
+				"width"  => width,
+				"height" => height
+			},
+
+			Request::Throttle(value) => object!{
+				"type"     => "throttle",
+				"throttle" => value
+			},
+
+			Request::Blank(value) => object!{
+				"type"  => "blank",
+				"blank" => value
+			},
+
+			Request::Safety(value) => object!{
+				"type"   => "safety",
+				"safety" => match value {
+					Safety::High   => "high",
+					Safety::Medium => "medium",
+					Safety::Low    => "low",
+				}
+			},
+
+			Request::Pointer(Pointer::Move { x, y }) => object!{
+				"type" => "pointer",
+				"move" => object!{
+					"x" => x,
+					"y" => y
+				}
+			},
+
+			Request::Pointer(Pointer::Button { x, y, button, press }) => object!{
+				"type"   => "pointer",
+				"button" => object!{
+					"x"      => x,
+					"y"      => y,
+					"button" => button,
+					"press"  => press
+				}
+			},
+
+			Request::Password(value) => object!{
+				"type"     => "password",
+				"password" => match value {
+					Password::Insert  => "insert",
+					Password::Delete  => "delete",
+					Password::Reset   => "reset",
+					Password::Check   => "check",
+					Password::Success => "success",
+					Password::Failure => "failure",
+				}
+			},
+
+			Request::Start => object!{
+				"type" => "start"
+			},
 
-						"stop" => {
-							Request::Stop
+			Request::Lock => object!{
+				"type" => "lock"
+			},
+
+			Request::Stop => object!{
+				"type" => "stop"
+			},
+
+			Request::Ping(id) => object!{
+				"type" => "ping",
+				"id"   => id
+			},
+		}
+	}
+}
+
+/// Fetch a possibly nested field from the message, making sure it's there and
+/// of the right type.
+fn field<'a, T, F>(message: &'a JsonValue, name: &str, expected: &'static str, get: F) -> Result<T, error::Protocol>
+	where F: FnOnce(&'a JsonValue) -> Option<T>
+{
+	let value = name.split('.').fold(message, |value, key| &value[key]);
+
+	if value.is_null() {
+		return Err(error::Protocol::MissingField(name.into()));
+	}
+
+	get(value).ok_or_else(|| error::Protocol::InvalidField {
+		field:    name.into(),
+		expected: expected,
+	})
+}
+
+impl Response {
+	/// Create an error response from the given error.
+	pub fn error(error: &error::Error, fatal: bool) -> Response {
+		Response::Error {
+			kind:    error.kind().into(),
+			message: error.to_string(),
+			fatal:   fatal,
+		}
+	}
+
+	/// Decode a response from its JSON representation.
+	pub fn from_json(message: &JsonValue) -> Result<Response, error::Protocol> {
+		Ok(match field(message, "type", "string", JsonValue::as_str)? {
+			"hello" => {
+				Response::Hello {
+					version:      field(message, "version", "integer", JsonValue::as_u32)?,
+					capabilities: message["capabilities"].members()
+						.filter_map(|c| c.as_str()).map(Into::into).collect(),
+				}
+			}
+
+			"initialized" => {
+				Response::Initialized
+			}
+
+			"started" => {
+				Response::Started
+			}
+
+			"stopped" => {
+				Response::Stopped
+			}
+
+			"heartbeat" => {
+				Response::Heartbeat {
+					frame: field(message, "frame", "integer", JsonValue::as_u64)?,
+				}
+			}
+
+			"pong" => {
+				Response::Pong(field(message, "id", "integer", JsonValue::as_u64)?)
+			}
+
+			"stats" => {
+				Response::Stats(Stats {
+					frames:     field(message, "frames", "integer", JsonValue::as_u64)?,
+					updates:    field(message, "updates", "integer", JsonValue::as_u64)?,
+					skipped:    field(message, "skipped", "integer", JsonValue::as_u64)?,
+					dropped:    field(message, "dropped", "integer", JsonValue::as_u64)?,
+					frame_time: Duration::from_micros(field(message, "frame-time", "integer", JsonValue::as_u64)?),
+					throttled:  field(message, "throttled", "boolean", JsonValue::as_bool)?,
+				})
+			}
+
+			"error" => {
+				Response::Error {
+					kind:    field(message, "kind", "string", JsonValue::as_str)?.into(),
+					message: field(message, "message", "string", JsonValue::as_str)?.into(),
+					fatal:   field(message, "fatal", "boolean", JsonValue::as_bool)?,
+				}
+			}
+
+			kind =>
+				return Err(error::Protocol::UnknownType(kind.into()))
+		})
+	}
+
+	/// Encode the response to its JSON representation.
+	pub fn to_json(&self) -> JsonValue {
+		match *self {
+			Response::Hello { version, ref capabilities } => object!{
+				"type"         => "hello",
+				"version"      => version,
+				"capabilities" => capabilities.clone()
+			},
+
+			Response::Initialized => object!{
+				"type" => "initialized"
+			},
+
+			Response::Started => object!{
+				"type" => "started"
+			},
+
+			Response::Stopped => object!{
+				"type" => "stopped"
+			},
+
+			Response::Heartbeat { frame } => object!{
+				"type"  => "heartbeat",
+				"frame" => frame
+			},
+
+			Response::Pong(id) => object!{
+				"type" => "pong",
+				"id"   => id
+			},
+
+			// The frame time is in microseconds.
+			Response::Stats(ref stats) => object!{
+				"type"       => "stats",
+				"frames"     => stats.frames,
+				"updates"    => stats.updates,
+				"skipped"    => stats.skipped,
+				"dropped"    => stats.dropped,
+				"frame-time" => stats.frame_time.as_micros() as u64,
+				"throttled"  => stats.throttled
+			},
+
+			Response::Error { ref kind, ref message, fatal } => object!{
+				"type"    => "error",
+				"kind"    => kind.clone(),
+				"message" => message.clone(),
+				"fatal"   => fatal
+			},
+		}
+	}
+}
+
+/// Spawn a thread decoding one message per line from the input.
+///
+/// The handler is called with the line number on malformed messages and
+/// returns whether to keep reading.
+pub(crate) fn reader<R, T, D, H>(input: R, sender: Sender<T>, decode: D, mut malformed: H)
+	where R: Read + Send + 'static,
+	      T: Send + 'static,
+	      D: Fn(&JsonValue) -> Result<T, error::Protocol> + Send + 'static,
+	      H: FnMut(usize, error::Protocol) -> bool + Send + 'static
+{
+	thread::spawn(move || {
+		for (number, line) in BufReader::new(input).lines().enumerate() {
+			let line = if let Ok(line) = line {
+				line
+			}
+			else {
+				break;
+			};
+
+			if line.trim().is_empty() {
+				continue;
+			}
+
+			let message = json::parse(&line)
+				.map_err(|err| error::Protocol::Json { line: number + 1, message: err.to_string() })
+				.and_then(|message| decode(&message));
+
+			match message {
+				Ok(message) => {
+					if sender.send(message).is_err() {
+						break;
+					}
+				}
+
+				Err(err) => {
+					if !malformed(number + 1, err) {
+						break;
+					}
+				}
+			}
+		}
+	});
+}
+
+/// Spawn a thread encoding one message per line to the output.
+///
+/// Every sender received on `flushes` is notified once all the messages
+/// queued before it have been written out.
+pub(crate) fn writer<W, T, E>(output: W, receiver: Receiver<T>, flushes: Receiver<Sender<()>>, encode: E)
+	where W: Write + Send + 'static,
+	      T: Send + 'static,
+	      E: Fn(&T) -> JsonValue + Send + 'static
+{
+	thread::spawn(move || {
+		let mut output = output;
+		let mut write  = |message: T| -> io::Result<()> {
+			output.write_all(json::stringify(encode(&message)).as_bytes())?;
+			output.write_all(b"\n")?;
+			output.flush()
+		};
+
+		// The other side going away is not our problem, just stop writing.
+		let result: io::Result<()> = (|| loop {
+			select! {
+				recv(receiver) -> message => {
+					if let Ok(message) = message {
+						write(message)?;
+					}
+					else {
+						return Ok(());
+					}
+				},
+
+				recv(flushes) -> done => {
+					if let Ok(done) = done {
+						while let Ok(message) = receiver.try_recv() {
+							write(message)?;
 						}
 
-						_ =>
-							continue
-					}).unwrap();
+						done.send(()).ok();
+					}
 				}
 			}
-		});
+		})();
 
-		// Writer.
-		thread::spawn(move || {
-			let mut output = output;
+		if let Err(err) = result {
+			warn!("could not write message: {}", err);
+		}
+	});
+}
 
-			while let Ok(response) = receiver.recv() {
-				output.write_all(json::stringify(match response {
-					Response::Initialized => object!{
-						"type" => "initialized"
-					},
+impl Channel {
+	/// Open the channel on the given input and output streams.
+	pub fn open<R: Read + Send + 'static, W: Write + Send + 'static>(input: R, output: W) -> error::Result<Channel> {
+		Channel::open_with(input, output, Options::default())
+	}
 
-					Response::Started => object!{
-						"type" => "started"
-					},
+	/// Open the channel on the given input and output streams with the given
+	/// options.
+	pub fn open_with<R: Read + Send + 'static, W: Write + Send + 'static>(input: R, output: W, options: Options) -> error::Result<Channel> {
+		let (sender, i_receiver) = unbounded();
+		let (i_sender, receiver) = unbounded();
+		let reporter             = i_sender.clone();
+		let (flusher, flushes)   = unbounded();
+		let failure              = Arc::new(Mutex::new(None));
+		let i_failure            = failure.clone();
 
-					Response::Stopped => object!{
-						"type" => "stopped"
-					},
-				}).as_bytes()).unwrap();
+		// Reader.
+		reader(input, sender, Request::from_json, move |line, err| {
+			if options.strict {
+				log::error!("malformed message on line {}: {}", line, err);
+			}
+			else {
+				warn!("malformed message on line {}: {}", line, err);
+			}
 
-				output.write_all(b"\n").unwrap();
+			if options.report || options.strict {
+				reporter.send(Response::error(&err.clone().into(), options.strict)).ok();
 			}
+
+			if options.strict {
+				*failure.lock().unwrap() = Some(err);
+				return false;
+			}
+
+			true
 		});
 
-		Ok(Channel {
+		// Writer.
+		writer(output, receiver, flushes, Response::to_json);
+
+		let mut channel = Channel {
 			receiver: i_receiver,
 			sender:   i_sender,
-		})
+			flusher:  flusher,
+			failure:  i_failure,
+
+			version:      0,
+			capabilities: Vec::new(),
+		};
+
+		channel.handshake()?;
+
+		Ok(channel)
+	}
+
+	/// Negotiate the protocol version and capabilities with the locker.
+	fn handshake(&mut self) -> error::Result<()> {
+		let (version, capabilities) = if let Ok(Request::Hello { version, capabilities }) = self.recv() {
+			(version, capabilities)
+		}
+		else {
+			// Malformed messages have already been reported in strict mode.
+			if let Some(failure) = self.failure() {
+				return Err(failure.into());
+			}
+
+			let error = error::Error::from(error::Protocol::Handshake);
+
+			self.send(Response::error(&error, true)).ok();
+			self.flush();
+
+			return Err(error);
+		};
+
+		if version < MINIMUM {
+			let error = error::Error::from(error::Protocol::Version {
+				locker: version,
+				saver:  (MINIMUM, VERSION),
+			});
+
+			self.send(Response::error(&error, true)).ok();
+			self.flush();
+
+			return Err(error);
+		}
+
+		self.version      = cmp::min(version, VERSION);
+		self.capabilities = capabilities.into_iter()
+			.filter(|c| CAPABILITIES.contains(&c.as_ref())).collect();
+
+		self.send(Response::Hello {
+			version:      self.version,
+			capabilities: self.capabilities.clone(),
+		}).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+		Ok(())
+	}
+
+	/// The negotiated protocol version.
+	pub fn version(&self) -> u32 {
+		self.version
+	}
+
+	/// The capabilities supported by both the locker and the saver.
+	pub fn capabilities(&self) -> &[String] {
+		&self.capabilities
+	}
+
+	/// Check if both sides support the given capability.
+	pub fn supports(&self, name: &str) -> bool {
+		self.capabilities.iter().any(|c| c == name)
+	}
+
+	/// The malformed message that closed the channel in strict mode, if any.
+	pub fn failure(&self) -> Option<error::Protocol> {
+		self.failure.lock().unwrap().clone()
 	}
 
 	/// Receive a message from the locker.
@@ -238,6 +700,46 @@ impl Channel {
 	pub fn send(&self, response: Response) -> Result<(), SendError<Response>> {
 		self.sender.send(response)
 	}
+
+	/// Wait until every message sent so far has been written out.
+	pub fn flush(&self) {
+		flush(&self.flusher);
+	}
+
+	/// A handle to send responses from other threads.
+	#[cfg(feature = "renderer")]
+	pub(crate) fn reporter(&self) -> Reporter {
+		Reporter {
+			sender:  self.sender.clone(),
+			flusher: self.flusher.clone(),
+		}
+	}
+}
+
+/// Sends responses from other threads, waiting for them to be written out.
+#[cfg(feature = "renderer")]
+#[derive(Clone)]
+pub(crate) struct Reporter {
+	sender:  Sender<Response>,
+	flusher: Sender<Sender<()>>,
+}
+
+#[cfg(feature = "renderer")]
+impl Reporter {
+	/// Send the response and wait for it to be written out.
+	pub fn report(&self, response: Response) {
+		if self.sender.send(response).is_ok() {
+			flush(&self.flusher);
+		}
+	}
+}
+
+fn flush(flusher: &Sender<Sender<()>>) {
+	let (done, flushed) = bounded(1);
+
+	if flusher.send(done).is_ok() {
+		flushed.recv().ok();
+	}
 }
 
 impl AsRef<Receiver<Request>> for Channel {
@@ -251,3 +753,147 @@ impl AsRef<Sender<Response>> for Channel {
 		&self.sender
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+	use std::time::Duration;
+	use crossbeam_channel::unbounded;
+	use json::object;
+	use crate::{Safety, Password, Pointer, Stats};
+	use crate::error::Protocol;
+	use super::*;
+
+	#[test]
+	fn requests() {
+		let requests = vec![
+			Request::Hello { version: 1, capabilities: vec!["ping".into()] },
+			Request::Config(object!{ "renderer" => object!{ "fps" => 30 } }),
+			Request::Target { display: Some(":0".into()), screen: 1, window: 42 },
+			Request::Target { display: None, screen: 0, window: 1 },
+			Request::Resize { width: 1920, height: 1080 },
+			Request::Throttle(true),
+			Request::Blank(false),
+			Request::Safety(Safety::High),
+			Request::Safety(Safety::Medium),
+			Request::Safety(Safety::Low),
+			Request::Pointer(Pointer::Move { x: -1, y: 2 }),
+			Request::Pointer(Pointer::Button { x: 3, y: 4, button: 1, press: true }),
+			Request::Password(Password::Insert),
+			Request::Password(Password::Delete),
+			Request::Password(Password::Reset),
+			Request::Password(Password::Check),
+			Request::Password(Password::Success),
+			Request::Password(Password::Failure),
+			Request::Start,
+			Request::Lock,
+			Request::Stop,
+			Request::Ping(7),
+		];
+
+		for request in requests {
+			assert_eq!(Request::from_json(&request.to_json()), Ok(request.clone()));
+		}
+	}
+
+	#[test]
+	fn responses() {
+		let responses = vec![
+			Response::Hello { version: 1, capabilities: vec![] },
+			Response::Initialized,
+			Response::Started,
+			Response::Stopped,
+			Response::Heartbeat { frame: 123 },
+			Response::Pong(7),
+			Response::Stats(Stats {
+				frames:     10,
+				updates:    11,
+				skipped:    1,
+				dropped:    2,
+				frame_time: Duration::from_micros(1500),
+				throttled:  true,
+			}),
+			Response::Error { kind: "panic".into(), message: "oops".into(), fatal: true },
+		];
+
+		for response in responses {
+			assert_eq!(Response::from_json(&response.to_json()), Ok(response.clone()));
+		}
+	}
+
+	#[test]
+	fn missing_field() {
+		assert_eq!(Request::from_json(&object!{ "type" => "resize", "width" => 1 }),
+			Err(Protocol::MissingField("height".into())));
+
+		assert_eq!(Request::from_json(&object!{ "version" => 1 }),
+			Err(Protocol::MissingField("type".into())));
+	}
+
+	#[test]
+	fn invalid_field() {
+		assert_eq!(Request::from_json(&object!{ "type" => "throttle", "throttle" => "yes" }),
+			Err(Protocol::InvalidField { field: "throttle".into(), expected: "boolean" }));
+
+		assert_eq!(Request::from_json(&object!{ "type" => "pointer", "move" => object!{ "x" => 1, "y" => "2" } }),
+			Err(Protocol::InvalidField { field: "move.y".into(), expected: "integer" }));
+	}
+
+	#[test]
+	fn invalid_value() {
+		assert_eq!(Request::from_json(&object!{ "type" => "safety", "safety" => "extreme" }),
+			Err(Protocol::InvalidValue { field: "safety".into(), value: "extreme".into() }));
+	}
+
+	#[test]
+	fn unknown_type() {
+		assert_eq!(Response::from_json(&object!{ "type" => "dance" }),
+			Err(Protocol::UnknownType("dance".into())));
+	}
+
+	/// Output that can be looked at after being handed to the channel.
+	#[derive(Clone, Default)]
+	struct Output(Arc<Mutex<Vec<u8>>>);
+
+	impl Write for Output {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.lock().unwrap().write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn handshake() {
+		let output = Output::default();
+		let result = Channel::open(Cursor::new(b"{\"type\":\"start\"}\n".to_vec()), output.clone());
+
+		assert!(matches!(result, Err(error::Error::Protocol(Protocol::Handshake))));
+
+		let written  = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+		let response = Response::from_json(&json::parse(written.trim()).unwrap()).unwrap();
+
+		assert!(matches!(response, Response::Error { ref kind, fatal: true, .. } if kind == "protocol"));
+	}
+
+	#[test]
+	fn json() {
+		let (sender, receiver) = unbounded();
+		let (errors, failures) = unbounded();
+		let input = Cursor::new(b"{\"type\":\"start\"}\n\n{\"type\":\n{\"type\":\"stop\"}\n".to_vec());
+
+		reader(input, sender, Request::from_json, move |line, err| {
+			errors.send((line, err)).unwrap();
+			true
+		});
+
+		assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![Request::Start, Request::Stop]);
+
+		match failures.recv() {
+			Ok((3, Protocol::Json { line: 3, .. })) => (),
+			failure => panic!("unexpected failure: {:?}", failure),
+		}
+	}
+}
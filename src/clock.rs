@@ -0,0 +1,204 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+//! Time sources for the render loop.
+
+use std::time::{Instant, Duration};
+use std::sync::{Mutex, Condvar};
+
+use crossbeam_channel::{self, bounded, Receiver, Sender};
+
+/// A source of time for the render loop.
+pub trait Clock: Send + Sync {
+	/// The time elapsed since the clock was created.
+	fn now(&self) -> Duration;
+
+	/// Get a receiver that fires once the given duration has elapsed.
+	fn after(&self, duration: Duration) -> Receiver<Instant>;
+
+	/// Block until the given duration has elapsed.
+	fn sleep(&self, duration: Duration) {
+		self.after(duration).recv().ok();
+	}
+}
+
+/// Wall clock time.
+#[derive(Debug)]
+pub struct Real {
+	start: Instant,
+}
+
+impl Real {
+	pub fn new() -> Self {
+		Real {
+			start: Instant::now(),
+		}
+	}
+}
+
+impl Default for Real {
+	fn default() -> Self {
+		Real::new()
+	}
+}
+
+impl Clock for Real {
+	fn now(&self) -> Duration {
+		self.start.elapsed()
+	}
+
+	fn after(&self, duration: Duration) -> Receiver<Instant> {
+		crossbeam_channel::after(duration)
+	}
+}
+
+/// Time that only moves when told to, so the render loop can be driven frame
+/// by frame.
+///
+/// Anything sleeping on the clock stays asleep until enough time has been
+/// advanced; use `park` to wait for the render loop to be done with a frame
+/// before moving time, or `step` to do both and jump to the next deadline.
+#[derive(Debug, Default)]
+pub struct Virtual {
+	state:  Mutex<State>,
+	parked: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+	now:    Duration,
+	timers: Vec<(Duration, Sender<Instant>)>,
+	parked: bool,
+}
+
+impl Virtual {
+	pub fn new() -> Self {
+		Virtual::default()
+	}
+
+	/// Block until something went to sleep on the clock since it was last
+	/// woken up.
+	pub fn park(&self) {
+		let mut state = self.state.lock().unwrap();
+
+		while !state.parked {
+			state = self.parked.wait(state).unwrap();
+		}
+	}
+
+	/// Wait for something to sleep on the clock, then move time forward to the
+	/// earliest deadline; returns how far time moved.
+	pub fn step(&self) -> Duration {
+		self.park();
+
+		let duration = {
+			let state = self.state.lock().unwrap();
+
+			state.timers.iter().map(|&(deadline, _)| deadline).min()
+				.map_or(Duration::from_secs(0), |deadline| deadline - state.now)
+		};
+
+		self.advance(duration);
+		duration
+	}
+
+	/// Move time forward, waking up anything whose deadline has passed.
+	pub fn advance(&self, duration: Duration) {
+		let mut state = self.state.lock().unwrap();
+		let     now   = state.now + duration;
+		let mut woken = false;
+
+		state.now = now;
+		state.timers.retain(|&(deadline, ref sender)| {
+			if deadline <= now {
+				sender.send(Instant::now()).ok();
+				woken = true;
+
+				false
+			}
+			else {
+				true
+			}
+		});
+
+		if woken {
+			state.parked = false;
+		}
+	}
+}
+
+impl Clock for Virtual {
+	fn now(&self) -> Duration {
+		self.state.lock().unwrap().now
+	}
+
+	fn after(&self, duration: Duration) -> Receiver<Instant> {
+		let mut state              = self.state.lock().unwrap();
+		let     (sender, receiver) = bounded(1);
+		let     deadline           = state.now + duration;
+
+		if duration == Duration::from_secs(0) {
+			sender.send(Instant::now()).unwrap();
+		}
+		else {
+			state.timers.push((deadline, sender));
+			state.parked = true;
+			self.parked.notify_all();
+		}
+
+		receiver
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::thread;
+	use std::time::Duration;
+	use super::*;
+
+	#[test]
+	fn step() {
+		let clock = Arc::new(Virtual::new());
+		let ticks = Arc::new(AtomicUsize::new(0));
+
+		{
+			let clock = clock.clone();
+			let ticks = ticks.clone();
+
+			thread::spawn(move || loop {
+				ticks.fetch_add(1, Ordering::SeqCst);
+				clock.sleep(Duration::from_millis(10));
+			});
+		}
+
+		clock.park();
+		assert_eq!(ticks.load(Ordering::SeqCst), 1);
+
+		assert_eq!(clock.step(), Duration::from_millis(10));
+		clock.park();
+		assert_eq!(ticks.load(Ordering::SeqCst), 2);
+		assert_eq!(clock.now(), Duration::from_millis(10));
+
+		// Not enough to reach the deadline.
+		clock.advance(Duration::from_millis(5));
+		clock.park();
+		assert_eq!(ticks.load(Ordering::SeqCst), 2);
+
+		clock.advance(Duration::from_millis(5));
+		clock.park();
+		assert_eq!(ticks.load(Ordering::SeqCst), 3);
+	}
+}
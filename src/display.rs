@@ -13,35 +13,101 @@
 //  0. You just DO WHAT THE FUCK YOU WANT TO.
 
 use std::ptr;
-use std::ffi::CString;
+use std::mem;
+use std::ffi::{CString, CStr};
 use std::rc::Rc;
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 
 use libc::c_int;
 use std::os::raw::c_void;
 use x11::{xlib, glx};
+use crossbeam_channel::{never, Receiver};
 use gl;
 use picto;
 
-use crate::error;
+use crate::{error, Monitor};
+use crate::screenshot;
+use crate::randr;
+use crate::events::Events;
+#[cfg(feature = "headless")]
+use crate::offscreen;
 
 pub struct Display {
 	context: Rc<gl::backend::Context>,
-	backend: Rc<Backend>,
+	backend: Surface,
+}
+
+/// Where to render to.
+#[derive(Clone, Debug)]
+pub enum Target {
+	/// An existing X11 window.
+	Window {
+		display: Option<String>,
+		screen:  i32,
+		window:  u64,
+	},
+
+	/// An offscreen framebuffer of the given size, needs neither an X server nor
+	/// a GPU.
+	#[cfg(feature = "headless")]
+	Offscreen {
+		width:  u32,
+		height: u32,
+	},
+}
+
+enum Surface {
+	Window(Rc<Backend>),
+
+	#[cfg(feature = "headless")]
+	Offscreen(Rc<offscreen::Backend>),
+}
+
+/// Changes made to the target or the screen behind the locker's back.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Change {
+	/// The target window has been resized.
+	Resize {
+		width:  u32,
+		height: u32,
+	},
+
+	/// The screen size or monitor layout changed.
+	Screen,
 }
 
 #[derive(Debug)]
 pub struct Backend {
 	display: *mut xlib::Display,
+	number:  i32,
 	root:    xlib::Window,
 	context: glx::GLXContext,
 	id:      xlib::Window,
 
 	screen: Cell<(u32, u32)>,
 	window: Cell<(u32, u32)>,
+
+	randr:    Option<randr::Library>,
+	event:    Option<c_int>,
+	monitors: RefCell<Vec<Monitor>>,
+
+	// Only an `Option` so it can be stopped before the connection is closed.
+	events: Option<Events>,
 }
 
 impl Display {
+	/// Open the Display for the given target.
+	pub fn new(target: &Target) -> error::Result<Display> {
+		match *target {
+			Target::Window { ref display, screen, window } =>
+				Display::open(display.as_ref().map(AsRef::as_ref), screen, window),
+
+			#[cfg(feature = "headless")]
+			Target::Offscreen { width, height } =>
+				Display::offscreen(width, height),
+		}
+	}
+
 	/// Open the matching Display.
 	pub fn open(name: Option<&str>, screen: i32, id: u64) -> error::Result<Display> {
 		unsafe {
@@ -57,8 +123,17 @@ impl Display {
 			let context = glx::glXCreateContext(display, info, ptr::null_mut(), 1)
 				.as_mut().ok_or(error::Display::Context)?;
 
+			// Follow size changes of the target and of the screen.
+			xlib::XSelectInput(display, id, xlib::StructureNotifyMask);
+			xlib::XSelectInput(display, root, xlib::StructureNotifyMask);
+
+			let randr  = randr::Library::open();
+			let event  = randr.as_ref().and_then(|r| r.select(display, root));
+			let events = Events::spawn(xlib::XConnectionNumber(display))?;
+
 			let backend = Rc::new(Backend {
 				display: display,
+				number:  screen,
 				root:    root,
 				context: context,
 				id:      id,
@@ -82,16 +157,35 @@ impl Display {
 					xlib::XGetGeometry(display, id, &mut root, &mut x, &mut y, &mut width, &mut height, &mut border, &mut depth);
 
 					(width as u32, height as u32)
-				})
+				}),
+
+				randr:    randr,
+				event:    event,
+				monitors: RefCell::new(Vec::new()),
+
+				events: Some(events),
 			});
 
+			backend.update_monitors();
+
 			Ok(Display {
-				backend: backend.clone(),
 				context: gl::backend::Context::new(backend.clone(), false, Default::default())?,
+				backend: Surface::Window(backend),
 			})
 		}
 	}
 
+	/// Open an offscreen Display of the given size.
+	#[cfg(feature = "headless")]
+	pub fn offscreen(width: u32, height: u32) -> error::Result<Display> {
+		let backend = Rc::new(offscreen::Backend::new(width, height)?);
+
+		Ok(Display {
+			context: unsafe { gl::backend::Context::new(backend.clone(), false, Default::default())? },
+			backend: Surface::Offscreen(backend),
+		})
+	}
+
 	/// Get the OpenGL context.
 	pub fn context(&self) -> Rc<gl::backend::Context> {
 		self.context.clone()
@@ -104,41 +198,258 @@ impl Display {
 
 	/// Resize the Display.
 	pub fn resize(&mut self, width: u32, height: u32) {
-		self.backend.window.set((width, height));
+		match self.backend {
+			Surface::Window(ref backend) => {
+				backend.window.set((width, height));
+				backend.update_monitors();
+			}
+
+			#[cfg(feature = "headless")]
+			Surface::Offscreen(ref backend) =>
+				backend.resize(width, height),
+		}
 	}
 
-	/// Take a screenshot.
+	/// Whether there are events from the X server waiting to be looked at.
+	pub fn pending(&self) -> bool {
+		match self.backend {
+			Surface::Window(ref backend) =>
+				unsafe { xlib::XPending(backend.display) > 0 },
+
+			#[cfg(feature = "headless")]
+			Surface::Offscreen(..) =>
+				false,
+		}
+	}
+
+	/// Get a receiver that fires once the X server sent something, check
+	/// `pending` first for events that were already read.
+	pub fn events(&self) -> Receiver<()> {
+		match self.backend {
+			Surface::Window(ref backend) =>
+				backend.events.as_ref().map_or_else(never, |e| e.wait().clone()),
+
+			#[cfg(feature = "headless")]
+			Surface::Offscreen(..) =>
+				never(),
+		}
+	}
+
+	/// Go through the pending events and apply the changes they bring.
+	pub fn changes(&mut self) -> Vec<Change> {
+		let backend = match self.backend {
+			Surface::Window(ref backend) =>
+				backend,
+
+			#[cfg(feature = "headless")]
+			Surface::Offscreen(..) =>
+				return Vec::new(),
+		};
+
+		let mut resize = None;
+		let mut screen = false;
+
+		unsafe {
+			while xlib::XPending(backend.display) > 0 {
+				let mut event = mem::zeroed::<xlib::XEvent>();
+				xlib::XNextEvent(backend.display, &mut event);
+
+				match event.get_type() {
+					xlib::ConfigureNotify if event.configure.window == backend.id => {
+						resize = Some((event.configure.width as u32, event.configure.height as u32));
+					}
+
+					xlib::ConfigureNotify if event.configure.window == backend.root => {
+						if let Some(randr) = backend.randr.as_ref() {
+							randr.update(&mut event);
+						}
+
+						screen = true;
+					}
+
+					kind if Some(kind) == backend.event => {
+						if let Some(randr) = backend.randr.as_ref() {
+							randr.update(&mut event);
+						}
+
+						screen = true;
+					}
+
+					_ => ()
+				}
+			}
+
+			if screen {
+				let width  = xlib::XDisplayWidth(backend.display, backend.number);
+				let height = xlib::XDisplayHeight(backend.display, backend.number);
+
+				backend.screen.set((width as u32, height as u32));
+				backend.update_monitors();
+			}
+		}
+
+		let mut changes = Vec::new();
+
+		if let Some((width, height)) = resize.filter(|&size| size != backend.window.get()) {
+			backend.window.set((width, height));
+			changes.push(Change::Resize { width, height });
+		}
+
+		if screen {
+			changes.push(Change::Screen);
+		}
+
+		changes
+	}
+
+	/// The size of the whole screen.
+	pub fn screen_dimensions(&self) -> (u32, u32) {
+		match self.backend {
+			Surface::Window(ref backend) =>
+				backend.screen.get(),
+
+			#[cfg(feature = "headless")]
+			Surface::Offscreen(ref backend) =>
+				backend.dimensions(),
+		}
+	}
+
+	/// The monitors showing the screen.
+	pub fn monitors(&self) -> Vec<Monitor> {
+		match self.backend {
+			Surface::Window(ref backend) =>
+				backend.monitors.borrow().clone(),
+
+			#[cfg(feature = "headless")]
+			Surface::Offscreen(ref backend) => {
+				let (width, height) = backend.dimensions();
+				vec![Monitor::screen(width, height)]
+			}
+		}
+	}
+
+	/// Synchronize buffer swaps with the vertical retrace, returns whether the
+	/// swap interval could be changed.
+	pub fn vsync(&self, enabled: bool) -> bool {
+		self.swap_interval(if enabled { 1 } else { 0 })
+	}
+
+	/// Wait for the given number of vertical retraces on every buffer swap,
+	/// returns whether the swap interval could be changed.
+	pub fn swap_interval(&self, interval: u32) -> bool {
+		match self.backend {
+			Surface::Window(ref backend) =>
+				backend.swap_interval(interval as i32),
+
+			#[cfg(feature = "headless")]
+			Surface::Offscreen(..) =>
+				false,
+		}
+	}
+
+	/// Read back the last presented frame.
+	pub fn capture(&self) -> Result<picto::buffer::Rgba, gl::ReadError> {
+		let image: gl::texture::RawImage2d<u8> = self.context.read_front_buffer()?;
+		let stride = image.width as usize * 4;
+		let mut data = Vec::with_capacity(image.data.len());
+
+		// OpenGL rows go from bottom to top.
+		for row in image.data.chunks(stride).rev() {
+			data.extend_from_slice(row);
+		}
+
+		Ok(picto::Buffer::from_raw(image.width, image.height, data).unwrap())
+	}
+
+	/// Take a screenshot, ask for `u16` channels to keep the precision of deep
+	/// color screens.
 	pub fn screenshot<P, C>(&self) -> picto::Buffer<P, C, Vec<C>>
 		where P: picto::Pixel<C> + picto::pixel::Write<C> + From<picto::color::Rgb>,
 		      C: picto::pixel::Channel
 	{
-		let (width, height)  = self.backend.screen.get();
+		let backend = match self.backend {
+			Surface::Window(ref backend) =>
+				backend,
+
+			// There is nothing behind an offscreen framebuffer.
+			#[cfg(feature = "headless")]
+			Surface::Offscreen(ref backend) => {
+				let (width, height) = backend.dimensions();
+				return picto::Buffer::<picto::color::Rgb, u8, _>::new(width, height).convert::<P, C>();
+			}
+		};
+
+		let (width, height) = backend.screen.get();
+
+		screenshot::capture(backend.display, backend.root, width, height).convert::<P, C>()
+	}
+}
+
+impl Backend {
+	/// Query the monitors again, treating the whole screen as one if XRandR is
+	/// not available.
+	fn update_monitors(&self) {
+		let mut monitors = self.randr.as_ref()
+			.map(|randr| unsafe { randr.monitors(self.display, self.root) })
+			.unwrap_or_default();
+
+		if monitors.is_empty() {
+			let (width, height) = self.screen.get();
+			monitors.push(Monitor::screen(width, height));
+		}
 
+		*self.monitors.borrow_mut() = monitors;
+	}
+
+	/// Set the swap interval through whichever swap control extension is
+	/// available.
+	fn swap_interval(&self, interval: i32) -> bool {
 		unsafe {
-			let ximage = xlib::XGetImage(self.backend.display, self.backend.root,
-				0, 0, width, height, xlib::XAllPlanes(), xlib::ZPixmap)
-					.as_mut().unwrap();
+			let extensions = glx::glXQueryExtensionsString(self.display, self.number);
+			if extensions.is_null() {
+				return false;
+			}
 
-			let r = (*ximage).red_mask;
-			let g = (*ximage).green_mask;
-			let b = (*ximage).blue_mask;
+			let extensions = CStr::from_ptr(extensions).to_string_lossy();
+			let supports   = |name: &str| extensions.split_whitespace().any(|e| e == name);
 
-			let mut image = picto::Buffer::<picto::color::Rgb, u8, _>::new(width, height);
+			if supports("GLX_EXT_swap_control") {
+				let function = self.function("glXSwapIntervalEXT");
+
+				if !function.is_null() {
+					let function = mem::transmute::<*const c_void, extern "C" fn(*mut xlib::Display, glx::GLXDrawable, i32)>(function);
+					function(self.display, self.id, interval);
+
+					return true;
+				}
+			}
 
-			for (x, y, mut px) in image.pixels_mut() {
-				let pixel = xlib::XGetPixel(ximage, x as c_int, y as c_int);
+			if supports("GLX_MESA_swap_control") {
+				let function = self.function("glXSwapIntervalMESA");
 
-				px.set(&picto::color::Rgb::new_u8(
-					((pixel & r) >> 16) as u8,
-					((pixel & g) >> 8)  as u8,
-					((pixel & b) >> 0)  as u8));
+				if !function.is_null() {
+					let function = mem::transmute::<*const c_void, extern "C" fn(u32) -> i32>(function);
+					return function(interval as u32) == 0;
+				}
 			}
 
-			xlib::XDestroyImage(ximage);
+			// SGI can't disable synchronization.
+			if supports("GLX_SGI_swap_control") && interval > 0 {
+				let function = self.function("glXSwapIntervalSGI");
 
-			image.convert::<P, C>()
+				if !function.is_null() {
+					let function = mem::transmute::<*const c_void, extern "C" fn(i32) -> i32>(function);
+					return function(interval) == 0;
+				}
+			}
+
+			false
 		}
 	}
+
+	unsafe fn function(&self, name: &str) -> *const c_void {
+		gl::backend::Backend::get_proc_address(self, name)
+	}
 }
 
 unsafe impl gl::backend::Backend for Backend {
@@ -176,6 +487,8 @@ unsafe impl gl::backend::Backend for Backend {
 
 impl Drop for Backend {
 	fn drop(&mut self) {
+		self.events.take();
+
 		unsafe {
 			glx::glXDestroyContext(self.display, self.context);
 			xlib::XCloseDisplay(self.display);
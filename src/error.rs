@@ -16,6 +16,9 @@ use std::fmt;
 use std::error;
 use std::io;
 use std::env;
+use std::process;
+#[cfg(feature = "renderer")]
+use std::path::PathBuf;
 
 #[cfg(feature = "renderer")]
 use gl;
@@ -30,9 +33,70 @@ pub enum Error {
 	ContextCreation(gl::IncompatibleOpenGl),
 	#[cfg(feature = "renderer")]
 	SwapBuffers(gl::SwapBuffersError),
+	#[cfg(feature = "renderer")]
+	Texture(gl::texture::TextureCreationError),
+	#[cfg(feature = "renderer")]
+	Capture(gl::ReadError),
+	#[cfg(feature = "renderer")]
+	Image(picto::Error),
+	#[cfg(feature = "renderer")]
+	Mismatch {
+		pixels: usize,
+		diff:   PathBuf,
+	},
 	Env(env::VarError),
 	Logger(log::SetLoggerError),
-	Protocol,
+	Protocol(Protocol),
+	Saver {
+		kind:    String,
+		message: String,
+	},
+	Timeout(&'static str),
+	Exited(process::ExitStatus),
+	Disconnected,
+	Panic(String),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Protocol {
+	/// The locker did not open with a handshake.
+	Handshake,
+
+	/// The locker and saver could not agree on a protocol version.
+	Version {
+		/// The version requested by the locker.
+		locker: u32,
+
+		/// The range of versions supported by the saver.
+		saver: (u32, u32),
+	},
+
+	/// The locker sent a message out of order.
+	Unexpected,
+
+	/// The message is not valid JSON.
+	Json {
+		line:    usize,
+		message: String,
+	},
+
+	/// The message type is unknown.
+	UnknownType(String),
+
+	/// A required field is missing.
+	MissingField(String),
+
+	/// A field has the wrong type.
+	InvalidField {
+		field:    String,
+		expected: &'static str,
+	},
+
+	/// A field has a value outside the allowed ones.
+	InvalidValue {
+		field: String,
+		value: String,
+	},
 }
 
 #[cfg(feature = "renderer")]
@@ -61,6 +125,12 @@ impl From<log::SetLoggerError> for Error {
 	}
 }
 
+impl From<Protocol> for Error {
+	fn from(value: Protocol) -> Self {
+		Error::Protocol(value)
+	}
+}
+
 #[cfg(feature = "renderer")]
 impl From<Display> for Error {
 	fn from(value: Display) -> Self {
@@ -86,9 +156,156 @@ impl From<gl::SwapBuffersError> for Error {
 	}
 }
 
+#[cfg(feature = "renderer")]
+impl From<gl::texture::TextureCreationError> for Error {
+	fn from(value: gl::texture::TextureCreationError) -> Self {
+		Error::Texture(value)
+	}
+}
+
+#[cfg(feature = "renderer")]
+impl From<gl::ReadError> for Error {
+	fn from(value: gl::ReadError) -> Self {
+		Error::Capture(value)
+	}
+}
+
+#[cfg(feature = "renderer")]
+impl From<picto::Error> for Error {
+	fn from(value: picto::Error) -> Self {
+		Error::Image(value)
+	}
+}
+
+impl Error {
+	/// A short machine readable name for the kind of error.
+	pub fn kind(&self) -> &'static str {
+		match *self {
+			Error::Io(..) =>
+				"io",
+
+			#[cfg(feature = "renderer")]
+			Error::ContextCreation(..) =>
+				"context",
+
+			#[cfg(feature = "renderer")]
+			Error::SwapBuffers(..) =>
+				"swap",
+
+			#[cfg(feature = "renderer")]
+			Error::Texture(..) =>
+				"texture",
+
+			#[cfg(feature = "renderer")]
+			Error::Capture(..) =>
+				"capture",
+
+			#[cfg(feature = "renderer")]
+			Error::Image(..) =>
+				"image",
+
+			#[cfg(feature = "renderer")]
+			Error::Mismatch { .. } =>
+				"mismatch",
+
+			Error::Env(..) =>
+				"env",
+
+			Error::Logger(..) =>
+				"logger",
+
+			Error::Protocol(..) =>
+				"protocol",
+
+			Error::Saver { .. } =>
+				"saver",
+
+			Error::Timeout(..) =>
+				"timeout",
+
+			Error::Exited(..) =>
+				"exited",
+
+			Error::Disconnected =>
+				"disconnected",
+
+			Error::Panic(..) =>
+				"panic",
+		}
+	}
+}
+
 impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> ::std::result::Result<(), fmt::Error> {
-		f.write_str(error::Error::description(self))
+		match *self {
+			#[cfg(feature = "renderer")]
+			Error::ContextCreation(ref err) =>
+				write!(f, "OpenGL error: {}", err),
+
+			#[cfg(feature = "renderer")]
+			Error::Texture(ref err) =>
+				write!(f, "Texture error: {}", err),
+
+			#[cfg(feature = "renderer")]
+			Error::Capture(ref err) =>
+				write!(f, "Capture error: {}", err),
+
+			#[cfg(feature = "renderer")]
+			Error::Image(ref err) =>
+				write!(f, "Image error: {}", err),
+
+			#[cfg(feature = "renderer")]
+			Error::Mismatch { pixels, ref diff } =>
+				write!(f, "{} pixels differ, see {}", pixels, diff.display()),
+
+			Error::Protocol(ref err) =>
+				write!(f, "Protocol error: {}", err),
+
+			Error::Saver { ref kind, ref message } =>
+				write!(f, "Saver error ({}): {}", kind, message),
+
+			Error::Timeout(name) =>
+				write!(f, "Timed out waiting for {}.", name),
+
+			Error::Exited(status) =>
+				write!(f, "Saver exited: {}", status),
+
+			Error::Panic(ref message) =>
+				write!(f, "Saver panicked: {}", message),
+
+			_ =>
+				f.write_str(error::Error::description(self))
+		}
+	}
+}
+
+impl fmt::Display for Protocol {
+	fn fmt(&self, f: &mut fmt::Formatter) -> ::std::result::Result<(), fmt::Error> {
+		match *self {
+			Protocol::Handshake =>
+				f.write_str("the locker did not send a handshake"),
+
+			Protocol::Version { locker, saver: (minimum, maximum) } =>
+				write!(f, "the locker speaks version {}, the saver supports {} to {}", locker, minimum, maximum),
+
+			Protocol::Unexpected =>
+				f.write_str("unexpected message"),
+
+			Protocol::Json { line, ref message } =>
+				write!(f, "invalid JSON on line {}: {}", line, message),
+
+			Protocol::UnknownType(ref kind) =>
+				write!(f, "unknown message type `{}`", kind),
+
+			Protocol::MissingField(ref field) =>
+				write!(f, "missing field `{}`", field),
+
+			Protocol::InvalidField { ref field, expected } =>
+				write!(f, "field `{}` should be {}", field, expected),
+
+			Protocol::InvalidValue { ref field, ref value } =>
+				write!(f, "invalid value `{}` for field `{}`", value, field),
+		}
 	}
 }
 
@@ -106,14 +323,45 @@ impl error::Error for Error {
 			Error::SwapBuffers(ref err) =>
 				err.description(),
 
+			#[cfg(feature = "renderer")]
+			Error::Texture(..) =>
+				"Texture error.",
+
+			#[cfg(feature = "renderer")]
+			Error::Capture(..) =>
+				"Capture error.",
+
+			#[cfg(feature = "renderer")]
+			Error::Image(..) =>
+				"Image error.",
+
+			#[cfg(feature = "renderer")]
+			Error::Mismatch { .. } =>
+				"Image mismatch.",
+
 			Error::Env(ref err) =>
 				err.description(),
 
 			Error::Logger(ref err) =>
 				err.description(),
 
-			Error::Protocol =>
+			Error::Protocol(..) =>
 				"Protocol error.",
+
+			Error::Saver { .. } =>
+				"Saver error.",
+
+			Error::Timeout(..) =>
+				"Timed out.",
+
+			Error::Exited(..) =>
+				"Saver exited.",
+
+			Error::Disconnected =>
+				"The locker went away.",
+
+			Error::Panic(..) =>
+				"Saver panicked.",
 		}
 	}
 }
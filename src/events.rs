@@ -0,0 +1,138 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+
+use std::io;
+use std::thread::{self, JoinHandle};
+
+use crossbeam_channel::{bounded, Receiver, Sender};
+use libc::c_int;
+
+/// Waits on the X connection from a separate thread, so the render loop can
+/// sleep until the server has something for it.
+///
+/// The connection is only polled, never read from, so Xlib stays on the render
+/// thread; anything already read by Xlib must be checked with `XPending`
+/// before waiting.
+#[derive(Debug)]
+pub struct Events {
+	arm:    Sender<()>,
+	ready:  Receiver<()>,
+	wake:   c_int,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl Events {
+	/// Start watching the given connection file descriptor.
+	pub fn spawn(fd: c_int) -> io::Result<Events> {
+		let mut pipe = [0; 2];
+
+		if unsafe { libc::pipe(pipe.as_mut_ptr()) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		let (i_arm, arm)     = bounded(1);
+		let (ready, i_ready) = bounded(1);
+		let stop             = pipe[0];
+
+		let thread = thread::spawn(move || {
+			let arm: Receiver<()> = arm;
+
+			// Only poll when asked to, the connection stays readable until Xlib gets
+			// around to reading it.
+			while arm.recv().is_ok() {
+				let mut fds = [
+					libc::pollfd { fd: fd,   events: libc::POLLIN, revents: 0 },
+					libc::pollfd { fd: stop, events: libc::POLLIN, revents: 0 },
+				];
+
+				while unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+					if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+						fds[1].revents = libc::POLLERR;
+						break;
+					}
+				}
+
+				if fds[1].revents != 0 || fds[0].revents & (libc::POLLERR | libc::POLLHUP) != 0 {
+					break;
+				}
+
+				ready.try_send(()).ok();
+			}
+
+			unsafe {
+				libc::close(stop);
+			}
+		});
+
+		Ok(Events {
+			arm:    i_arm,
+			ready:  i_ready,
+			wake:   pipe[1],
+			thread: Some(thread),
+		})
+	}
+
+	/// Get a receiver that fires once the connection has something to read.
+	pub fn wait(&self) -> &Receiver<()> {
+		// Drop any leftover wakeup from an earlier wait.
+		while self.ready.try_recv().is_ok() { }
+
+		self.arm.try_send(()).ok();
+		&self.ready
+	}
+}
+
+impl Drop for Events {
+	fn drop(&mut self) {
+		// Closing the write end hangs up the pipe, waking the thread whether it's
+		// polling or waiting to be asked to.
+		unsafe {
+			libc::close(self.wake);
+		}
+
+		self.arm.try_send(()).ok();
+
+		if let Some(thread) = self.thread.take() {
+			thread.join().ok();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use super::*;
+
+	#[test]
+	fn wait() {
+		let mut pipe = [0; 2];
+		assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, 0);
+
+		let events = Events::spawn(pipe[0]).unwrap();
+		assert!(events.wait().recv_timeout(Duration::from_millis(50)).is_err());
+
+		unsafe {
+			libc::write(pipe[1], b"x".as_ptr() as *const _, 1);
+		}
+
+		assert!(events.wait().recv_timeout(Duration::from_secs(1)).is_ok());
+		drop(events);
+
+		unsafe {
+			libc::close(pipe[0]);
+			libc::close(pipe[1]);
+		}
+	}
+}
@@ -16,6 +16,8 @@
 
 use std::io;
 use std::env;
+#[cfg(feature = "renderer")]
+use std::time::Duration;
 
 #[cfg(feature = "renderer")]
 pub use gl;
@@ -28,7 +30,7 @@ pub use log::{debug, error, info, log, log_enabled, trace, warn};
 #[macro_use]
 mod util;
 
-mod error;
+pub mod error;
 pub use error::{Result, Error};
 
 mod state;
@@ -43,23 +45,83 @@ pub use password::Password;
 pub mod pointer;
 pub use pointer::Pointer;
 
-mod channel;
+mod stats;
+pub use stats::Stats;
+
+pub mod channel;
 pub use channel::{Request, Response, Channel};
 
+mod locker;
+pub use locker::Locker;
+
+mod process;
+pub use process::SaverProcess;
+
+#[cfg(feature = "renderer")]
+mod timing;
+#[cfg(feature = "renderer")]
+pub use timing::Timing;
+
+#[cfg(feature = "renderer")]
+mod redraw;
+#[cfg(feature = "renderer")]
+pub use redraw::Redraw;
+
 #[cfg(feature = "renderer")]
 mod saver;
 #[cfg(feature = "renderer")]
 pub use saver::Saver;
 
 #[cfg(feature = "renderer")]
-mod renderer;
+pub mod clock;
+#[cfg(feature = "renderer")]
+pub use clock::Clock;
+
+#[cfg(feature = "renderer")]
+pub mod renderer;
 #[cfg(feature = "renderer")]
 pub use renderer::Renderer;
 
+#[cfg(feature = "renderer")]
+mod watchdog;
+
 #[cfg(feature = "renderer")]
 mod display;
 #[cfg(feature = "renderer")]
-pub use display::Display;
+pub use display::{Display, Target, Change};
+
+#[cfg(feature = "renderer")]
+mod events;
+
+#[cfg(feature = "renderer")]
+mod monitor;
+#[cfg(feature = "renderer")]
+pub use monitor::Monitor;
+
+#[cfg(feature = "renderer")]
+mod library;
+
+#[cfg(feature = "renderer")]
+mod randr;
+
+#[cfg(feature = "renderer")]
+mod screen;
+#[cfg(feature = "renderer")]
+pub use screen::{Screen, Uv};
+
+#[cfg(feature = "renderer")]
+mod screenshot;
+
+#[cfg(feature = "headless")]
+mod offscreen;
+
+#[cfg(feature = "renderer")]
+pub mod capture;
+
+#[cfg(feature = "renderer")]
+mod mock;
+#[cfg(feature = "renderer")]
+pub use mock::Mock;
 
 
 /// Initialize the saver.
@@ -82,9 +144,33 @@ pub fn init() -> Result<Channel> {
 		builder.init();
 	}
 
-	Channel::open(io::stdin(), io::stdout())
+	// Handle malformed messages as requested.
+	let options = match env::var("SCREENRUSTER_PROTOCOL").as_ref().map(AsRef::as_ref) {
+		Ok("strict") =>
+			channel::Options { report: true, strict: true },
+
+		Ok("report") =>
+			channel::Options { report: true, strict: false },
+
+		_ =>
+			channel::Options::default()
+	};
+
+	Channel::open_with(io::stdin(), io::stdout(), options)
 }
 
+/// How long to wait for the saver to stop when the locker goes away.
+#[cfg(feature = "renderer")]
+const SHUTDOWN: Duration = Duration::from_secs(5);
+
+/// The exit status used when the saver panicked.
+#[cfg(feature = "renderer")]
+pub const PANIC: i32 = 101;
+
+/// The exit status used when the saver stopped making progress.
+#[cfg(feature = "renderer")]
+pub const HUNG: i32 = 102;
+
 /// Run the saver.
 #[cfg(feature = "renderer")]
 pub fn run<S: Saver + Send + 'static>(mut saver: S) -> Result<()> {
@@ -94,35 +180,115 @@ pub fn run<S: Saver + Send + 'static>(mut saver: S) -> Result<()> {
 				value
 			}
 			else {
-				break;
+				break false;
 			}
 		);
 	}
 
-	use crossbeam_channel::select;
+	use crossbeam_channel::{select, tick, never};
+	use std::{panic, thread};
 
 	let channel = init()?;
 
-	if let Ok(Request::Config(config)) = channel.recv() {
+	// Report panics to the locker before going down, the render thread catches
+	// and reports its own.
+	{
+		let reporter = channel.reporter();
+		let hook     = panic::take_hook();
+
+		panic::set_hook(Box::new(move |info| {
+			if thread::current().name() != Some(renderer::THREAD) {
+				reporter.report(Response::Error {
+					kind:    "panic".into(),
+					message: util::panic_message(info.payload()),
+					fatal:   true,
+				});
+			}
+
+			hook(info);
+		}));
+	}
+
+	// Tell the locker why we're leaving, it would only see the pipe close
+	// otherwise.
+	macro_rules! unexpected {
+		() => ({
+			// Malformed messages have already been reported in strict mode.
+			if let Some(failure) = channel.failure() {
+				return Err(failure.into());
+			}
+
+			let error = Error::from(error::Protocol::Unexpected);
+
+			channel.send(channel::Response::error(&error, true)).ok();
+			channel.flush();
+
+			return Err(error);
+		});
+	}
+
+	let (options, watchdog, stats) = if let Ok(Request::Config(config)) = channel.recv() {
+		let options  = renderer::Options::from_config(&config);
+		let watchdog = watchdog::Options::from_config(&config);
+		let stats    = config["stats"]["interval"].as_u64()
+			.filter(|&v| v > 0).map(Duration::from_millis);
+
 		saver.config(config);
+
+		(options, watchdog, stats)
 	}
 	else {
-		return Err(Error::Protocol);
-	}
+		unexpected!();
+	};
 
-	let renderer = if let Ok(Request::Target { display, screen, window }) = channel.recv() {
-		Renderer::new(display, screen, window, saver)
+	let mut renderer = if let Ok(Request::Target { display, screen, window }) = channel.recv() {
+		Renderer::with_options(Target::Window { display, screen, window }, options, saver)
 	}
 	else {
-		return Err(Error::Protocol);
+		unexpected!();
+	};
+
+	let heartbeat = channel.supports("heartbeat");
+	let watchdog  = watchdog::Watchdog::spawn(renderer.progress(), watchdog);
+
+	// Only report statistics if the locker asked for them.
+	let stats = match stats {
+		Some(interval) if channel.supports("stats") =>
+			tick(interval),
+
+		_ =>
+			never(),
 	};
 
-	'main: loop {
+	let disconnected = 'main: loop {
+		// The writer stops on the first error, so a failed send means the locker
+		// is gone just like a failed receive.
+		macro_rules! send {
+			($response:expr) => (
+				if channel.send($response).is_err() {
+					break 'main true;
+				}
+			);
+		}
+
 		select! {
 			recv(channel.as_ref()) -> message => {
-				match exit!(message) {
+				let message = if let Ok(message) = message {
+					message
+				}
+				else {
+					break 'main true;
+				};
+
+				match message {
+					channel::Request::Hello { .. } => {
+						warn!("unexpected hello after the handshake");
+						send!(channel::Response::error(&error::Protocol::Unexpected.into(), false));
+					}
+
 					channel::Request::Target { .. } | channel::Request::Config(..) => {
-						unreachable!();
+						warn!("unexpected setup request after the handshake");
+						send!(channel::Response::error(&error::Protocol::Unexpected.into(), false));
 					}
 
 					channel::Request::Resize { width, height } => {
@@ -160,28 +326,101 @@ pub fn run<S: Saver + Send + 'static>(mut saver: S) -> Result<()> {
 					channel::Request::Stop => {
 						renderer.stop().unwrap();
 					}
+
+					channel::Request::Ping(id) => {
+						renderer.ping(id).unwrap();
+					}
 				}
 			},
 
 			recv(renderer.as_ref()) -> message => {
 				match exit!(message) {
 					renderer::Response::Initialized => {
-						channel.send(channel::Response::Initialized).unwrap();
+						send!(channel::Response::Initialized);
 					}
 
 					renderer::Response::Started => {
-						channel.send(channel::Response::Started).unwrap();
+						send!(channel::Response::Started);
 					}
 
 					renderer::Response::Stopped => {
-						break 'main;
+						break 'main false;
+					}
+
+					renderer::Response::Frame(..) => {
+						unreachable!();
+					}
+
+					renderer::Response::Pong(id) => {
+						send!(channel::Response::Pong(id));
+					}
+
+					renderer::Response::Error(err) => {
+						error!("renderer failed: {}", err);
+						channel.send(channel::Response::error(&err, true)).ok();
+
+						// The saver is in an unknown state, make sure the locker got the
+						// error and leave so it can restart or fall back.
+						if let Error::Panic(..) = err {
+							channel.flush();
+							std::process::exit(PANIC);
+						}
+
+						return Err(err);
+					}
+				}
+			},
+
+			recv(watchdog.as_ref()) -> event => {
+				match event {
+					Ok(watchdog::Event::Heartbeat(frame)) => {
+						if heartbeat {
+							send!(channel::Response::Heartbeat { frame });
+						}
+					}
+
+					// The render thread can't be stopped, so tell the locker and leave.
+					Ok(watchdog::Event::Hung(elapsed)) => {
+						error!("no frame rendered in {}ms, giving up", elapsed.as_millis());
+						channel.send(channel::Response::error(&Error::Timeout("frame"), true)).ok();
+						channel.flush();
+
+						std::process::exit(HUNG);
+					}
+
+					Err(..) => {
+						unreachable!();
 					}
 				}
+			},
+
+			recv(stats) -> _ => {
+				send!(channel::Response::Stats(renderer.stats()));
 			}
 		}
+	};
+
+	// The locker went away, stop the saver and clean up before leaving.
+	if disconnected {
+		warn!("the locker went away, stopping");
+
+		renderer.stop().ok();
+		if !renderer.wait(SHUTDOWN) {
+			warn!("the saver did not stop in time");
+		}
+
+		if let Some(err) = channel.failure() {
+			return Err(err.into());
+		}
+
+		return Err(Error::Disconnected);
+	}
+
+	if channel.send(channel::Response::Stopped).is_err() {
+		return Err(Error::Disconnected);
 	}
 
-	channel.send(channel::Response::Stopped).unwrap();
+	channel.flush();
 
 	Ok(())
 }
@@ -0,0 +1,60 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+
+use std::ffi::CStr;
+
+use libc::c_void;
+
+/// A shared library loaded at runtime, for the X extensions the saver can do
+/// without.
+#[derive(Debug)]
+pub struct Library {
+	handle: *mut c_void,
+}
+
+impl Library {
+	/// Load the library with the given name, if it's installed.
+	pub fn open(name: &CStr) -> Option<Library> {
+		let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_LAZY | libc::RTLD_LOCAL) };
+
+		if handle.is_null() {
+			return None;
+		}
+
+		Some(Library {
+			handle: handle,
+		})
+	}
+
+	/// Look up the symbol with the given name.
+	pub fn symbol(&self, name: &CStr) -> Option<*mut c_void> {
+		let symbol = unsafe { libc::dlsym(self.handle, name.as_ptr()) };
+
+		if symbol.is_null() {
+			None
+		}
+		else {
+			Some(symbol)
+		}
+	}
+}
+
+impl Drop for Library {
+	fn drop(&mut self) {
+		unsafe {
+			libc::dlclose(self.handle);
+		}
+	}
+}
@@ -0,0 +1,141 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use crossbeam_channel::{never, unbounded, Receiver, RecvError, RecvTimeoutError, Sender, SendError};
+use log::warn;
+
+use crate::error;
+use crate::channel::{self, Request, Response};
+
+/// The locker end of the channel, used to drive a saver.
+pub struct Locker {
+	receiver: Receiver<Response>,
+	sender:   Sender<Request>,
+
+	version:      u32,
+	capabilities: Vec<String>,
+}
+
+impl Locker {
+	/// Open the channel on the saver output and input streams, advertising all
+	/// the supported capabilities.
+	pub fn open<R: Read + Send + 'static, W: Write + Send + 'static>(input: R, output: W) -> error::Result<Locker> {
+		Locker::open_with(input, output, channel::CAPABILITIES)
+	}
+
+	/// Open the channel on the saver output and input streams, advertising the
+	/// given capabilities.
+	pub fn open_with<R: Read + Send + 'static, W: Write + Send + 'static>(input: R, output: W, capabilities: &[&str]) -> error::Result<Locker> {
+		let (sender, i_receiver) = unbounded();
+		let (i_sender, receiver) = unbounded();
+
+		// Reader.
+		channel::reader(input, sender, Response::from_json, |line, err| {
+			warn!("malformed message on line {}: {}", line, err);
+			true
+		});
+
+		// Writer.
+		channel::writer(output, receiver, never(), Request::to_json);
+
+		let mut locker = Locker {
+			receiver: i_receiver,
+			sender:   i_sender,
+
+			version:      0,
+			capabilities: Vec::new(),
+		};
+
+		locker.handshake(capabilities)?;
+
+		Ok(locker)
+	}
+
+	/// Negotiate the protocol version and capabilities with the saver.
+	fn handshake(&mut self, capabilities: &[&str]) -> error::Result<()> {
+		self.send(Request::Hello {
+			version:      channel::VERSION,
+			capabilities: capabilities.iter().map(|&c| c.into()).collect(),
+		}).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+		match self.recv() {
+			Ok(Response::Hello { version, capabilities }) => {
+				if version < channel::MINIMUM || version > channel::VERSION {
+					return Err(error::Protocol::Version {
+						locker: channel::VERSION,
+						saver:  (version, version),
+					}.into());
+				}
+
+				self.version      = version;
+				self.capabilities = capabilities;
+
+				Ok(())
+			}
+
+			Ok(Response::Error { kind, message, .. }) => {
+				Err(error::Error::Saver { kind, message })
+			}
+
+			_ => {
+				Err(error::Protocol::Handshake.into())
+			}
+		}
+	}
+
+	/// The negotiated protocol version.
+	pub fn version(&self) -> u32 {
+		self.version
+	}
+
+	/// The capabilities supported by both the locker and the saver.
+	pub fn capabilities(&self) -> &[String] {
+		&self.capabilities
+	}
+
+	/// Check if both sides support the given capability.
+	pub fn supports(&self, name: &str) -> bool {
+		self.capabilities.iter().any(|c| c == name)
+	}
+
+	/// Receive a message from the saver.
+	pub fn recv(&self) -> Result<Response, RecvError> {
+		self.receiver.recv()
+	}
+
+	/// Receive a message from the saver, giving up after the timeout.
+	pub fn recv_timeout(&self, timeout: Duration) -> Result<Response, RecvTimeoutError> {
+		self.receiver.recv_timeout(timeout)
+	}
+
+	/// Send a message to the saver.
+	pub fn send(&self, request: Request) -> Result<(), SendError<Request>> {
+		self.sender.send(request)
+	}
+}
+
+impl AsRef<Receiver<Response>> for Locker {
+	fn as_ref(&self) -> &Receiver<Response> {
+		&self.receiver
+	}
+}
+
+impl AsRef<Sender<Request>> for Locker {
+	fn as_ref(&self) -> &Sender<Request> {
+		&self.sender
+	}
+}
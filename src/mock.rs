@@ -0,0 +1,367 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+use std::thread;
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use log::warn;
+
+use crate::error;
+use crate::{Channel, Locker, Request, Response, Saver, State, Timing};
+
+/// How long to wait for the saver to answer.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How many updates a stopping saver gets to reach `State::None`.
+const STEPS: usize = 10_000;
+
+/// The duration of an update step.
+const STEP: Duration = Duration::from_millis(15);
+
+/// An in-memory locker driving a `Saver` over the real protocol, without any
+/// display.
+///
+/// Requests go through the locker and saver codecs, then are dispatched to the
+/// saver as the renderer would, except nothing is drawn: `initialize`,
+/// `monitors`, `resize` and `render` are never called.
+///
+/// Like the renderer, before `Start` throttling and blanking are only taken
+/// note of and pointer and password events are dropped.
+pub struct Mock<S: Saver> {
+	saver:   S,
+	locker:  Locker,
+	channel: Channel,
+	states:  Vec<State>,
+	updates: u32,
+	started: bool,
+}
+
+impl<S: Saver> Mock<S> {
+	/// Connect a mock locker to the given saver.
+	pub fn new(saver: S) -> error::Result<Mock<S>> {
+		let (saver_input, locker_output) = pipe();
+		let (locker_input, saver_output) = pipe();
+
+		let locker  = thread::spawn(move || Locker::open(locker_input, locker_output));
+		let channel = Channel::open(saver_input, saver_output)?;
+		let locker  = locker.join().unwrap()?;
+		let state   = saver.state();
+
+		Ok(Mock {
+			saver:   saver,
+			locker:  locker,
+			channel: channel,
+			states:  vec![state],
+			updates: 0,
+			started: false,
+		})
+	}
+
+	/// Send a request to the saver and wait for it to be handled.
+	pub fn send(&mut self, request: Request) -> error::Result<&mut Self> {
+		if self.locker.send(request).is_err() {
+			return Err(io::Error::from(io::ErrorKind::BrokenPipe).into());
+		}
+
+		let request = self.channel.recv()
+			.map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+		match request {
+			Request::Hello { .. } => {
+				return Err(error::Protocol::Unexpected.into());
+			}
+
+			Request::Config(config) => {
+				self.saver.config(config);
+			}
+
+			Request::Target { .. } => {
+				self.respond(Response::Initialized)?;
+			}
+
+			Request::Resize { .. } => (),
+
+			Request::Throttle(value) => {
+				if self.started {
+					self.saver.throttle(value);
+				}
+			}
+
+			Request::Blank(value) => {
+				if self.started {
+					self.saver.blank(value);
+				}
+			}
+
+			Request::Safety(value) => {
+				self.saver.safety(value);
+			}
+
+			Request::Pointer(..) | Request::Password(..) if !self.started => {
+				warn!("unexpected event before start: {:?}", request);
+			}
+
+			Request::Pointer(pointer) => {
+				self.saver.pointer(pointer);
+			}
+
+			Request::Password(password) => {
+				self.saver.password(password);
+			}
+
+			Request::Start => {
+				self.started = true;
+				self.saver.start();
+				self.record();
+				self.respond(Response::Started)?;
+			}
+
+			Request::Lock => {
+				self.saver.lock();
+			}
+
+			// The renderer gives up without telling the saver.
+			Request::Stop if !self.started => {
+				self.respond(Response::Stopped)?;
+			}
+
+			Request::Stop => {
+				self.saver.stop();
+				self.record();
+
+				for _ in 0 .. STEPS {
+					if self.saver.state() == State::None {
+						break;
+					}
+
+					self.update(1);
+				}
+
+				if self.saver.state() != State::None {
+					return Err(error::Error::Timeout("stopped"));
+				}
+
+				self.respond(Response::Stopped)?;
+			}
+
+			Request::Ping(id) => {
+				self.respond(Response::Pong(id))?;
+			}
+		}
+
+		self.record();
+
+		Ok(self)
+	}
+
+	/// Send the given requests in order.
+	pub fn script<I: IntoIterator<Item = Request>>(&mut self, requests: I) -> error::Result<&mut Self> {
+		for request in requests {
+			self.send(request)?;
+		}
+
+		Ok(self)
+	}
+
+	/// Advance the saver by the given number of update steps.
+	pub fn update(&mut self, steps: usize) -> &mut Self {
+		for _ in 0 .. steps {
+			self.saver.update_with(&Timing {
+				elapsed: STEP * self.updates,
+				step:    STEP,
+				alpha:   0.0,
+				frame:   0,
+			});
+
+			self.updates += 1;
+			self.record();
+		}
+
+		self
+	}
+
+	/// Receive the next response from the saver.
+	pub fn recv(&self) -> error::Result<Response> {
+		self.locker.recv_timeout(TIMEOUT)
+			.map_err(|_| error::Error::Timeout("response"))
+	}
+
+	/// Receive all the responses already sent by the saver.
+	pub fn responses(&self) -> Vec<Response> {
+		let mut responses = Vec::new();
+
+		while let Ok(response) = self.locker.recv_timeout(Duration::from_millis(10)) {
+			responses.push(response);
+		}
+
+		responses
+	}
+
+	/// The current saver state.
+	pub fn state(&self) -> State {
+		self.saver.state()
+	}
+
+	/// Every state the saver went through, in order, without repetitions.
+	pub fn states(&self) -> &[State] {
+		&self.states
+	}
+
+	/// The saver being tested.
+	pub fn saver(&self) -> &S {
+		&self.saver
+	}
+
+	/// The saver being tested.
+	pub fn saver_mut(&mut self) -> &mut S {
+		&mut self.saver
+	}
+
+	fn record(&mut self) {
+		let state = self.saver.state();
+
+		if self.states.last() != Some(&state) {
+			self.states.push(state);
+		}
+	}
+
+	fn respond(&self, response: Response) -> error::Result<()> {
+		self.channel.send(response)
+			.map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe).into())
+	}
+}
+
+/// Create an in-memory pipe.
+fn pipe() -> (PipeReader, PipeWriter) {
+	let (sender, receiver) = unbounded();
+
+	(PipeReader { receiver: receiver, buffer: Vec::new(), position: 0 },
+	 PipeWriter { sender: sender })
+}
+
+struct PipeReader {
+	receiver: Receiver<Vec<u8>>,
+	buffer:   Vec<u8>,
+	position: usize,
+}
+
+impl Read for PipeReader {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		if self.position >= self.buffer.len() {
+			if let Ok(chunk) = self.receiver.recv() {
+				self.buffer   = chunk;
+				self.position = 0;
+			}
+			else {
+				return Ok(0);
+			}
+		}
+
+		let available = &self.buffer[self.position ..];
+		let size      = available.len().min(buf.len());
+
+		buf[.. size].copy_from_slice(&available[.. size]);
+		self.position += size;
+
+		Ok(size)
+	}
+}
+
+struct PipeWriter {
+	sender: Sender<Vec<u8>>,
+}
+
+impl Write for PipeWriter {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.sender.send(buf.to_vec())
+			.map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use json::object;
+	use crate::{Request, Response, Saver, State, Pointer};
+	use super::*;
+
+	struct Fade {
+		state:    State,
+		pointers: usize,
+	}
+
+	impl Saver for Fade {
+		fn pointer(&mut self, _pointer: Pointer) {
+			self.pointers += 1;
+		}
+
+		fn start(&mut self) {
+			self.state = State::Begin;
+		}
+
+		fn stop(&mut self) {
+			self.state = State::End;
+		}
+
+		fn state(&self) -> State {
+			self.state
+		}
+
+		fn update(&mut self) {
+			self.state = match self.state {
+				State::Begin => State::Running,
+				State::End   => State::None,
+				state        => state,
+			};
+		}
+	}
+
+	#[test]
+	fn lifecycle() {
+		let mut mock = Mock::new(Fade { state: State::None, pointers: 0 }).unwrap();
+
+		mock.script(vec![
+			Request::Config(object!{}),
+			Request::Target { display: None, screen: 0, window: 0 },
+			Request::Pointer(Pointer::Move { x: 0, y: 0 }),
+			Request::Start,
+		]).unwrap();
+
+		// Pointer events before start are dropped like the renderer does.
+		assert_eq!(mock.saver().pointers, 0);
+
+		mock.update(1);
+		mock.script(vec![
+			Request::Pointer(Pointer::Move { x: 1, y: 1 }),
+			Request::Ping(42),
+			Request::Stop,
+		]).unwrap();
+
+		assert_eq!(mock.saver().pointers, 1);
+		assert_eq!(mock.responses(), vec![
+			Response::Initialized,
+			Response::Started,
+			Response::Pong(42),
+			Response::Stopped,
+		]);
+		assert_eq!(mock.states(), &[State::None, State::Begin, State::Running, State::End, State::None]);
+	}
+}
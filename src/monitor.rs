@@ -0,0 +1,45 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+/// A monitor showing part of the screen.
+#[derive(PartialEq, Copy, Clone, Default, Debug)]
+pub struct Monitor {
+	/// The horizontal position within the screen.
+	pub x: i32,
+
+	/// The vertical position within the screen.
+	pub y: i32,
+
+	/// The width in pixels.
+	pub width: u32,
+
+	/// The height in pixels.
+	pub height: u32,
+
+	/// The refresh rate in Hz, if known.
+	pub refresh: Option<f32>,
+}
+
+impl Monitor {
+	/// A monitor covering a whole screen of the given size.
+	pub fn screen(width: u32, height: u32) -> Self {
+		Monitor {
+			x:       0,
+			y:       0,
+			width:   width,
+			height:  height,
+			refresh: None,
+		}
+	}
+}
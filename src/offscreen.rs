@@ -0,0 +1,119 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::ptr;
+use std::ffi::CString;
+use std::cell::{Cell, RefCell};
+
+use libc::{c_int, c_uint, c_uchar, c_char};
+use std::os::raw::c_void;
+use gl;
+
+use crate::error;
+
+type OSMesaContext = *mut c_void;
+
+const OSMESA_RGBA:      c_uint = 0x1908;
+const GL_UNSIGNED_BYTE: c_uint = 0x1401;
+
+#[link(name = "OSMesa")]
+extern "C" {
+	fn OSMesaCreateContextExt(format: c_uint, depth: c_int, stencil: c_int, accum: c_int, share: OSMesaContext) -> OSMesaContext;
+	fn OSMesaDestroyContext(context: OSMesaContext);
+	fn OSMesaMakeCurrent(context: OSMesaContext, buffer: *mut c_void, kind: c_uint, width: c_int, height: c_int) -> c_uchar;
+	fn OSMesaGetCurrentContext() -> OSMesaContext;
+	fn OSMesaGetProcAddress(name: *const c_char) -> *const c_void;
+}
+
+/// Software rendering into memory through OSMesa, needs neither an X server
+/// nor a GPU.
+#[derive(Debug)]
+pub struct Backend {
+	context: OSMesaContext,
+	buffer:  RefCell<Vec<u8>>,
+	size:    Cell<(u32, u32)>,
+}
+
+impl Backend {
+	/// Create a framebuffer of the given size.
+	pub fn new(width: u32, height: u32) -> error::Result<Backend> {
+		unsafe {
+			let context = OSMesaCreateContextExt(OSMESA_RGBA, 24, 0, 0, ptr::null_mut())
+				.as_mut().ok_or(error::Display::Context)?;
+
+			let backend = Backend {
+				context: context,
+				buffer:  RefCell::new(Vec::new()),
+				size:    Cell::new((0, 0)),
+			};
+
+			backend.resize(width, height);
+
+			Ok(backend)
+		}
+	}
+
+	/// Reallocate the framebuffer.
+	pub fn resize(&self, width: u32, height: u32) {
+		let mut buffer = self.buffer.borrow_mut();
+		*buffer = vec![0; width as usize * height as usize * 4];
+		self.size.set((width, height));
+
+		unsafe {
+			OSMesaMakeCurrent(self.context, buffer.as_mut_ptr() as *mut _, GL_UNSIGNED_BYTE,
+				width as c_int, height as c_int);
+		}
+	}
+
+	/// The framebuffer dimensions.
+	pub fn dimensions(&self) -> (u32, u32) {
+		self.size.get()
+	}
+}
+
+unsafe impl gl::backend::Backend for Backend {
+	fn swap_buffers(&self) -> Result<(), gl::SwapBuffersError> {
+		Ok(())
+	}
+
+	unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+		let addr = CString::new(symbol.as_bytes()).unwrap();
+		OSMesaGetProcAddress(addr.as_ptr())
+	}
+
+	fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+		self.size.get()
+	}
+
+	fn is_current(&self) -> bool {
+		unsafe {
+			OSMesaGetCurrentContext() == self.context
+		}
+	}
+
+	unsafe fn make_current(&self) {
+		let (width, height) = self.size.get();
+
+		OSMesaMakeCurrent(self.context, self.buffer.borrow_mut().as_mut_ptr() as *mut _, GL_UNSIGNED_BYTE,
+			width as c_int, height as c_int);
+	}
+}
+
+impl Drop for Backend {
+	fn drop(&mut self) {
+		unsafe {
+			OSMesaDestroyContext(self.context);
+		}
+	}
+}
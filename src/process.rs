@@ -0,0 +1,265 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::io::{self, Read};
+use std::ffi::OsStr;
+use std::process::{Command, Child, ChildStderr, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, Duration};
+use std::thread;
+
+use crossbeam_channel::{bounded, RecvTimeoutError};
+use json::JsonValue;
+use log::warn;
+
+use crate::error;
+use crate::{Locker, Request, Response};
+
+/// How long to wait for the saver to answer by default.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A saver executable driven from the locker side.
+pub struct SaverProcess {
+	child:   Child,
+	locker:  Locker,
+	stderr:  Arc<Mutex<Vec<u8>>>,
+	timeout: Duration,
+}
+
+impl SaverProcess {
+	/// Spawn the saver at the given path.
+	pub fn spawn<P: AsRef<OsStr>>(path: P) -> error::Result<SaverProcess> {
+		SaverProcess::spawn_with(Command::new(path))
+	}
+
+	/// Spawn the saver from the given command, the standard streams are
+	/// replaced with pipes.
+	pub fn spawn_with(mut command: Command) -> error::Result<SaverProcess> {
+		let mut child = command
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()?;
+
+		let input  = child.stdout.take().unwrap();
+		let output = child.stdin.take().unwrap();
+		let stderr = collect(child.stderr.take().unwrap());
+
+		// The handshake blocks on the saver, so give up if it never answers.
+		let (sender, receiver) = bounded(1);
+		thread::spawn(move || {
+			sender.send(Locker::open(input, output)).ok();
+		});
+
+		let locker = match receiver.recv_timeout(TIMEOUT) {
+			Ok(Ok(locker)) => {
+				locker
+			}
+
+			Ok(Err(err)) => {
+				child.kill().ok();
+				child.wait().ok();
+
+				return Err(err);
+			}
+
+			Err(_) => {
+				child.kill().ok();
+				child.wait().ok();
+
+				return Err(error::Error::Timeout("hello"));
+			}
+		};
+
+		Ok(SaverProcess {
+			child:   child,
+			locker:  locker,
+			stderr:  stderr,
+			timeout: TIMEOUT,
+		})
+	}
+
+	/// Change how long to wait for the saver to answer.
+	pub fn set_timeout(&mut self, timeout: Duration) {
+		self.timeout = timeout;
+	}
+
+	/// The process identifier of the saver.
+	pub fn id(&self) -> u32 {
+		self.child.id()
+	}
+
+	/// The underlying channel.
+	pub fn locker(&self) -> &Locker {
+		&self.locker
+	}
+
+	/// Everything the saver wrote to its standard error so far.
+	pub fn stderr(&self) -> String {
+		String::from_utf8_lossy(&self.stderr.lock().unwrap()).into_owned()
+	}
+
+	/// Send the saver configuration.
+	pub fn config(&mut self, config: JsonValue) -> error::Result<()> {
+		self.send(Request::Config(config))
+	}
+
+	/// Send the drawable target and wait for the saver to be initialized.
+	pub fn target(&mut self, display: Option<String>, screen: i32, window: u64) -> error::Result<()> {
+		self.send(Request::Target { display, screen, window })?;
+		self.expect("initialized", |r| matches!(r, Response::Initialized))
+	}
+
+	/// Start the saver and wait for it to be started.
+	pub fn start(&mut self) -> error::Result<()> {
+		self.send(Request::Start)?;
+		self.expect("started", |r| matches!(r, Response::Started))
+	}
+
+	/// Check the saver is still rendering, waiting for the matching pong.
+	pub fn ping(&mut self, id: u64) -> error::Result<()> {
+		self.send(Request::Ping(id))?;
+		self.expect("pong", |r| *r == Response::Pong(id))
+	}
+
+	/// Stop the saver, wait for it to be stopped and for the process to exit.
+	///
+	/// A saver that does not comply in time is killed.
+	pub fn stop(&mut self) -> error::Result<ExitStatus> {
+		self.send(Request::Stop)?;
+		self.expect("stopped", |r| matches!(r, Response::Stopped))?;
+
+		let deadline = Instant::now() + self.timeout;
+		loop {
+			if let Some(status) = self.child.try_wait()? {
+				return Ok(status);
+			}
+
+			if Instant::now() >= deadline {
+				warn!("saver did not exit after stopping, killing it");
+				return self.kill();
+			}
+
+			thread::sleep(Duration::from_millis(10));
+		}
+	}
+
+	/// Kill the saver and return its exit status.
+	pub fn kill(&mut self) -> error::Result<ExitStatus> {
+		self.child.kill().ok();
+		Ok(self.child.wait()?)
+	}
+
+	/// Check if the saver has exited without blocking.
+	pub fn try_wait(&mut self) -> error::Result<Option<ExitStatus>> {
+		Ok(self.child.try_wait()?)
+	}
+
+	/// Send a request to the saver.
+	pub fn send(&mut self, request: Request) -> error::Result<()> {
+		if self.locker.send(request).is_err() {
+			return Err(self.exited());
+		}
+
+		Ok(())
+	}
+
+	/// Wait for the expected response, killing the saver if it takes too long.
+	fn expect<F: Fn(&Response) -> bool>(&mut self, name: &'static str, expected: F) -> error::Result<()> {
+		let deadline = Instant::now() + self.timeout;
+
+		loop {
+			let timeout = deadline.saturating_duration_since(Instant::now());
+
+			match self.locker.recv_timeout(timeout) {
+				Ok(ref response) if expected(response) => {
+					return Ok(());
+				}
+
+				Ok(Response::Error { kind, message, fatal: true }) => {
+					self.kill().ok();
+					return Err(error::Error::Saver { kind, message });
+				}
+
+				Ok(Response::Heartbeat { .. }) | Ok(Response::Stats(..)) => (),
+
+				Ok(Response::Error { kind, message, fatal: false }) => {
+					warn!("saver error ({}): {}", kind, message);
+				}
+
+				Ok(response) => {
+					warn!("unexpected response while waiting for {}: {:?}", name, response);
+				}
+
+				Err(RecvTimeoutError::Timeout) => {
+					warn!("saver did not answer with {} in time, killing it", name);
+					self.kill().ok();
+
+					return Err(error::Error::Timeout(name));
+				}
+
+				Err(RecvTimeoutError::Disconnected) => {
+					return Err(self.exited());
+				}
+			}
+		}
+	}
+
+	/// Build the error for a saver that went away.
+	fn exited(&mut self) -> error::Error {
+		match self.child.wait() {
+			Ok(status) =>
+				error::Error::Exited(status),
+
+			Err(err) =>
+				err.into()
+		}
+	}
+}
+
+impl Drop for SaverProcess {
+	fn drop(&mut self) {
+		if let Ok(None) = self.child.try_wait() {
+			self.kill().ok();
+		}
+	}
+}
+
+/// Collect the standard error of the saver in the background.
+fn collect(mut stderr: ChildStderr) -> Arc<Mutex<Vec<u8>>> {
+	let buffer   = Arc::new(Mutex::new(Vec::new()));
+	let i_buffer = buffer.clone();
+
+	thread::spawn(move || {
+		let mut chunk = [0; 4096];
+
+		loop {
+			match stderr.read(&mut chunk) {
+				Ok(0) =>
+					break,
+
+				Ok(size) =>
+					i_buffer.lock().unwrap().extend_from_slice(&chunk[.. size]),
+
+				Err(ref err) if err.kind() == io::ErrorKind::Interrupted =>
+					continue,
+
+				Err(_) =>
+					break,
+			}
+		}
+	});
+
+	buffer
+}
@@ -0,0 +1,150 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::mem;
+use std::slice;
+use std::ffi::CStr;
+
+use libc::{c_int, c_void};
+use x11::{xlib, xrandr};
+
+use crate::Monitor;
+use crate::library;
+
+type GetScreenResourcesCurrent = unsafe extern "C" fn(*mut xlib::Display, xlib::Window) -> *mut xrandr::XRRScreenResources;
+type FreeScreenResources       = unsafe extern "C" fn(*mut xrandr::XRRScreenResources);
+type GetCrtcInfo               = unsafe extern "C" fn(*mut xlib::Display, *mut xrandr::XRRScreenResources, xrandr::RRCrtc) -> *mut xrandr::XRRCrtcInfo;
+type FreeCrtcInfo              = unsafe extern "C" fn(*mut xrandr::XRRCrtcInfo);
+type QueryExtension            = unsafe extern "C" fn(*mut xlib::Display, *mut c_int, *mut c_int) -> xlib::Bool;
+type SelectInput               = unsafe extern "C" fn(*mut xlib::Display, xlib::Window, c_int);
+type UpdateConfiguration       = unsafe extern "C" fn(*mut xlib::XEvent) -> c_int;
+
+/// XRandR, loaded at runtime so it stays an optional dependency.
+#[derive(Debug)]
+pub struct Library {
+	// Kept loaded for as long as the functions are around.
+	_library: library::Library,
+
+	get_screen_resources_current: GetScreenResourcesCurrent,
+	free_screen_resources:        FreeScreenResources,
+	get_crtc_info:                GetCrtcInfo,
+	free_crtc_info:               FreeCrtcInfo,
+	query_extension:              QueryExtension,
+	select_input:                 SelectInput,
+	update_configuration:         UpdateConfiguration,
+}
+
+impl Library {
+	/// Load the library, if it's installed.
+	pub fn open() -> Option<Library> {
+		let library = library::Library::open(CStr::from_bytes_with_nul(b"libXrandr.so.2\0").unwrap())?;
+
+		macro_rules! symbol {
+			($name:expr, $kind:ty) => (unsafe {
+				mem::transmute::<*mut c_void, $kind>(
+					library.symbol(CStr::from_bytes_with_nul(concat!($name, "\0").as_bytes()).unwrap())?)
+			});
+		}
+
+		Some(Library {
+			get_screen_resources_current: symbol!("XRRGetScreenResourcesCurrent", GetScreenResourcesCurrent),
+			free_screen_resources:        symbol!("XRRFreeScreenResources", FreeScreenResources),
+			get_crtc_info:                symbol!("XRRGetCrtcInfo", GetCrtcInfo),
+			free_crtc_info:               symbol!("XRRFreeCrtcInfo", FreeCrtcInfo),
+			query_extension:              symbol!("XRRQueryExtension", QueryExtension),
+			select_input:                 symbol!("XRRSelectInput", SelectInput),
+			update_configuration:         symbol!("XRRUpdateConfiguration", UpdateConfiguration),
+
+			_library: library,
+		})
+	}
+
+	/// Ask for screen change events on the given root window, returns the
+	/// event type they'll come as.
+	pub unsafe fn select(&self, display: *mut xlib::Display, root: xlib::Window) -> Option<c_int> {
+		let mut event = 0;
+		let mut error = 0;
+
+		if (self.query_extension)(display, &mut event, &mut error) == xlib::False {
+			return None;
+		}
+
+		(self.select_input)(display, root, xrandr::RRScreenChangeNotifyMask);
+		Some(event + xrandr::RRScreenChangeNotify)
+	}
+
+	/// Let Xlib know about the new screen configuration.
+	pub unsafe fn update(&self, event: &mut xlib::XEvent) {
+		(self.update_configuration)(event);
+	}
+
+	/// Query the active monitors on the screen of the given root window.
+	pub unsafe fn monitors(&self, display: *mut xlib::Display, root: xlib::Window) -> Vec<Monitor> {
+		let resources = if let Some(resources) = (self.get_screen_resources_current)(display, root).as_mut() {
+			resources
+		}
+		else {
+			return Vec::new();
+		};
+
+		let crtcs    = slice::from_raw_parts(resources.crtcs, resources.ncrtc as usize);
+		let modes    = slice::from_raw_parts(resources.modes, resources.nmode as usize);
+		let mut list = Vec::new();
+
+		for &crtc in crtcs {
+			let info = if let Some(info) = (self.get_crtc_info)(display, resources, crtc).as_mut() {
+				info
+			}
+			else {
+				continue;
+			};
+
+			// Disabled CRTCs have no mode.
+			if info.mode != 0 && info.noutput > 0 {
+				list.push(Monitor {
+					x:       info.x,
+					y:       info.y,
+					width:   info.width,
+					height:  info.height,
+					refresh: modes.iter().find(|m| m.id == info.mode).and_then(refresh),
+				});
+			}
+
+			(self.free_crtc_info)(info);
+		}
+
+		(self.free_screen_resources)(resources);
+
+		list
+	}
+}
+
+/// Calculate the refresh rate of a mode.
+fn refresh(mode: &xrandr::XRRModeInfo) -> Option<f32> {
+	let mut lines = mode.vTotal as f64;
+
+	if mode.modeFlags & xrandr::RR_DoubleScan as xrandr::XRRModeFlags != 0 {
+		lines *= 2.0;
+	}
+
+	if mode.modeFlags & xrandr::RR_Interlace as xrandr::XRRModeFlags != 0 {
+		lines /= 2.0;
+	}
+
+	if mode.hTotal == 0 || lines == 0.0 {
+		return None;
+	}
+
+	Some((mode.dotClock as f64 / (mode.hTotal as f64 * lines)) as f32)
+}
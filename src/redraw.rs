@@ -0,0 +1,37 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::time::Duration;
+
+/// When the saver needs to be drawn again.
+///
+/// While waiting no updates are run, nor are they caught up on afterwards, the
+/// time spent is added to `Timing::elapsed` instead.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Redraw {
+	/// On every frame.
+	Always,
+
+	/// Only when a request comes in.
+	Idle,
+
+	/// After the given duration, or earlier if a request comes in.
+	After(Duration),
+}
+
+impl Default for Redraw {
+	fn default() -> Redraw {
+		Redraw::Always
+	}
+}
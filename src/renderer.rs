@@ -13,19 +13,63 @@
 //  0. You just DO WHAT THE FUCK YOU WANT TO.
 
 use std::time::{Instant, Duration};
-use std::thread;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::panic::{self, AssertUnwindSafe};
+use std::cmp;
 
-use crossbeam_channel::{unbounded, Receiver, Sender, SendError};
+use crossbeam_channel::{never, unbounded, Receiver, RecvTimeoutError, Sender, SendError, Select};
 use gl::{self, Surface};
 use picto;
-use log::warn;
-
-use crate::{Display, Saver, State, Safety, Password, Pointer};
+use json;
+use log::{debug, warn};
+
+use crate::{Display, Saver, State, Safety, Password, Pointer, Clock, Timing, Redraw, Stats, Screen};
+use crate::clock;
+use crate::display::{Target, Change};
+use crate::error;
+use crate::util;
 use crate::util::DurationExt;
 
+/// The name of the render thread.
+pub(crate) const THREAD: &str = "renderer";
+
 pub struct Renderer {
 	receiver: Receiver<Response>,
 	sender:   Sender<Request>,
+	thread:   Option<JoinHandle<()>>,
+	progress: Arc<Progress>,
+	stats:    Arc<Mutex<Stats>>,
+}
+
+/// Progress made by the render thread, used to tell a hung saver apart from
+/// an idle one.
+#[derive(Default, Debug)]
+pub struct Progress {
+	frame: AtomicU64,
+	idle:  AtomicBool,
+}
+
+impl Progress {
+	/// The number of completed iterations of the render loop.
+	pub fn frame(&self) -> u64 {
+		self.frame.load(Ordering::SeqCst)
+	}
+
+	/// Whether the renderer is waiting for requests instead of running the
+	/// saver.
+	pub fn idle(&self) -> bool {
+		self.idle.load(Ordering::SeqCst)
+	}
+
+	fn advance(&self) {
+		self.frame.fetch_add(1, Ordering::SeqCst);
+	}
+
+	fn set_idle(&self, value: bool) {
+		self.idle.store(value, Ordering::SeqCst);
+	}
 }
 
 #[derive(Debug)]
@@ -59,9 +103,15 @@ pub enum Request {
 
 	/// Stop the rendering.
 	Stop,
+
+	/// Read back the frame rendered after the given number of update steps.
+	Capture(u64),
+
+	/// Answer with a `Pong` from the render thread.
+	Ping(u64),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub enum Response {
 	/// The renderer has been initialized.
 	Initialized,
@@ -71,169 +121,521 @@ pub enum Response {
 
 	/// The rendering has stopped.
 	Stopped,
+
+	/// A captured frame.
+	Frame(picto::buffer::Rgba),
+
+	/// Answer to a `Ping`.
+	Pong(u64),
+
+	/// The renderer failed and has given up.
+	Error(error::Error),
 }
 
-const STEP: u64 = 15_000_000;
+/// Renderer settings.
+#[derive(Clone)]
+pub struct Options {
+	/// The duration of a fixed update step.
+	pub step: Duration,
 
-impl Renderer {
-	pub fn new<S: Saver + Send + 'static>(display: Option<String>, screen: i32, window: u64, mut saver: S) -> Renderer {
-		let (sender, i_receiver) = unbounded();
-		let (i_sender, receiver) = unbounded();
+	/// The maximum frame rate.
+	pub fps: u32,
 
-		thread::spawn(move || {
-			let mut display  = Display::open(display.as_ref().map(AsRef::as_ref), screen, window).unwrap();
-			let mut blank    = false;
-			let mut throttle = false;
-			let mut skip     = false;
-
-			// Put the current screen in a texture.
-			let texture = {
-				let image = display.screenshot::<picto::color::Rgba, u8>();
-				let size  = image.dimensions();
-				let image = gl::texture::RawImage2d::from_raw_rgba_reversed(
-					&image.into_raw(), size);
-
-				gl::texture::SrgbTexture2d::new(&display.context(), image).unwrap()
-			};
-
-			// Initialize the saver.
-			saver.initialize(display.context());
-			sender.send(Response::Initialized).unwrap();
-
-			// Handle some initial settings before starting.
-			while let Ok(message) = receiver.recv() {
-				match message {
-					Request::Start => {
-						break;
-					}
+	/// The maximum frame rate while throttled.
+	pub throttled: u32,
 
-					Request::Throttle(value) => {
-						throttle = value;
-					}
+	/// The maximum number of update steps to run per frame, any lag left over
+	/// is discarded and reported to the saver, `None` to always catch up.
+	pub catch_up: Option<u32>,
 
-					Request::Blank(value) => {
-						blank = value;
-					}
+	/// Pace frames with the vertical retrace when supported, skipping retraces
+	/// to stay close to the frame rate when the refresh rate is known.
+	pub vsync: bool,
 
-					Request::Safety(level) => {
-						saver.safety(level);
-					}
+	/// The source of time for the loop.
+	pub clock: Arc<dyn Clock>,
+}
 
-					Request::Lock => {
-						saver.lock();
-					}
+impl Default for Options {
+	fn default() -> Self {
+		Options {
+			step:      Duration::from_millis(15),
+			fps:       60,
+			throttled: 30,
+			catch_up:  Some(8),
+			vsync:     true,
+			clock:     Arc::new(clock::Real::new()),
+		}
+	}
+}
 
-					event => {
-						warn!("unexpected event before start: {:?}", event);
-					}
-				}
-			}
+impl Options {
+	/// Read the settings from the `renderer` section of the saver
+	/// configuration, falling back to the defaults.
+	pub fn from_config(config: &json::JsonValue) -> Self {
+		let mut options = Options::default();
+		let     config  = &config["renderer"];
+
+		if let Some(value) = config["step"].as_u64().filter(|&v| v > 0) {
+			options.step = Duration::from_millis(value);
+		}
+
+		if let Some(value) = config["fps"].as_u32() {
+			options.fps = value;
+		}
 
-			// Start the saver.
-			saver.start();
-			sender.send(Response::Started).unwrap();
+		if let Some(value) = config["throttled"].as_u32() {
+			options.throttled = value;
+		}
 
-			let mut lag      = 0;
-			let mut previous = Instant::now();
+		// Zero means always catch up.
+		if let Some(value) = config["catch-up"].as_u32() {
+			options.catch_up = Some(value).filter(|&v| v > 0);
+		}
 
-			'render: loop {
-				let now     = Instant::now();
-				let elapsed = now.duration_since(previous);
+		if let Some(value) = config["vsync"].as_bool() {
+			options.vsync = value;
+		}
 
-				// Calculate accumulated lag.
-				previous  = now;
-				lag      += elapsed.as_nanosecs();
+		options
+	}
 
-				// Update the state by 15ms steps.
-				while lag >= STEP {
-					saver.update();
+	/// The maximum frame rate.
+	fn fps(&self, throttle: bool) -> u32 {
+		cmp::max(if throttle { self.throttled } else { self.fps }, 1)
+	}
 
-					if saver.state() == State::None {
-						break 'render;
-					}
+	/// The minimum duration of a frame.
+	fn frame(&self, throttle: bool) -> Duration {
+		Duration::from_secs(1) / self.fps(throttle)
+	}
+}
 
-					lag -= STEP;
+impl Renderer {
+	pub fn new<S: Saver + Send + 'static>(display: Option<String>, screen: i32, window: u64, saver: S) -> Renderer {
+		Renderer::open(Target::Window { display, screen, window }, saver)
+	}
+
+	/// Render the saver on the given target.
+	pub fn open<S: Saver + Send + 'static>(target: Target, saver: S) -> Renderer {
+		Renderer::with_options(target, Options::default(), saver)
+	}
+
+	/// Render the saver on the given target, timing the loop with the given
+	/// clock.
+	pub fn with_clock<S: Saver + Send + 'static>(target: Target, clock: Arc<dyn Clock>, saver: S) -> Renderer {
+		Renderer::with_options(target, Options { clock: clock, .. Default::default() }, saver)
+	}
+
+	/// Render the saver on the given target with the given settings.
+	pub fn with_options<S: Saver + Send + 'static>(target: Target, options: Options, saver: S) -> Renderer {
+		let (sender, i_receiver) = unbounded();
+		let (i_sender, receiver) = unbounded();
+		let progress             = Arc::new(Progress::default());
+		let i_progress           = progress.clone();
+		let stats                = Arc::new(Mutex::new(Stats::default()));
+		let i_stats              = stats.clone();
+
+		let thread = thread::Builder::new().name(THREAD.into()).spawn(move || {
+			let reporter = sender.clone();
+
+			// Any panic, from the saver or the renderer itself, is reported instead
+			// of silently killing the thread.
+			let result = panic::catch_unwind(AssertUnwindSafe(move || {
+				macro_rules! fatal {
+					($body:expr) => (
+						match $body {
+							Ok(value) => {
+								value
+							}
+
+							Err(err) => {
+								sender.send(Response::Error(err.into())).unwrap();
+								return;
+							}
+						}
+					);
+				}
+
+				// The saver goes after the display, so its resources are dropped
+				// before the context.
+				let mut display  = fatal!(Display::new(&target));
+				let mut saver    = saver;
+				let mut blank    = false;
+				let mut throttle = false;
+				let mut updates  = 0;
+				let mut skipped  = 0;
+				let mut dropped  = 0;
+				let mut capture  = None;
+
+				// Pace frames with vsync if possible, and make sure it's off otherwise.
+				let vsync = display.vsync(options.vsync) && options.vsync;
+
+				// Knowing the refresh rate, vsync can also keep to the frame rate by
+				// waiting for as many retraces per frame as needed.
+				let refresh = display.monitors().iter().filter_map(|m| m.refresh)
+					.fold(None, |fastest: Option<f32>, rate| Some(fastest.map_or(rate, |f| f.max(rate))));
+				let paced = vsync && refresh.is_some();
+
+				macro_rules! pace {
+					() => (
+						if let Some(refresh) = refresh.filter(|_| paced) {
+							let retraces = (refresh / options.fps(throttle) as f32).round();
+							display.swap_interval(cmp::max(retraces as u32, 1));
+						}
+					);
+				}
+
+				// Put the current screen in a texture.
+				let mut screen = fatal!(Screen::capture(&display));
+
+				// Release everything in order before telling the renderer stopped.
+				macro_rules! stopped {
+					() => ({
+						drop(screen);
+						drop(saver);
+						drop(display);
+
+						sender.send(Response::Stopped).unwrap();
+						return;
+					});
 				}
 
-				// Handle requests.
-				while let Ok(event) = receiver.try_recv() {
-					match event {
-						Request::Resize { width, height } => {
-							display.resize(width, height);
-							saver.resize(display.context());
+				// Initialize the saver.
+				saver.monitors(&display.monitors());
+				saver.initialize(display.context());
+				sender.send(Response::Initialized).unwrap();
+
+				// Handle some initial settings before starting.
+				progress.set_idle(true);
+				let mut started = false;
+				while let Ok(message) = receiver.recv() {
+					match message {
+						Request::Start => {
+							started = true;
+							break;
+						}
+
+						Request::Stop => {
+							break;
 						}
 
 						Request::Throttle(value) => {
-							saver.throttle(value);
 							throttle = value;
 						}
 
 						Request::Blank(value) => {
-							saver.blank(value);
 							blank = value;
 						}
 
 						Request::Safety(level) => {
-							saver.safety(level)
+							saver.safety(level);
 						}
 
-						Request::Pointer(pointer) => {
-							saver.pointer(pointer);
+						Request::Lock => {
+							saver.lock();
 						}
 
-						Request::Password(password) => {
-							saver.password(password);
+						Request::Capture(steps) => {
+							capture = Some(updates + steps);
 						}
 
-						Request::Lock => {
-							saver.lock();
+						Request::Ping(id) => {
+							sender.send(Response::Pong(id)).unwrap();
 						}
 
-						Request::Stop => {
-							saver.stop();
+						event => {
+							warn!("unexpected event before start: {:?}", event);
 						}
-
-						_ => ()
 					}
 				}
 
-				// While throttling we skip every other frame, so it stays at 30 FPS.
-				if throttle {
-					if skip {
-						skip = false;
+				if !started {
+					stopped!();
+				}
+
+				// Start the saver.
+				pace!();
+				progress.set_idle(false);
+				saver.start();
+				sender.send(Response::Started).unwrap();
+
+				// Resize the viewport and tell the saver about it.
+				macro_rules! resize {
+					($width:expr, $height:expr) => ({
+						display.resize($width, $height);
+						screen.set_monitors(display.monitors());
+						saver.monitors(screen.monitors());
+						saver.resize(display.context());
+					});
+				}
+
+				let     clock    = options.clock.clone();
+				let     step     = options.step.as_nanosecs();
+				let mut lag      = 0;
+				let mut previous = clock.now();
+				let mut frames   = 0;
+				let mut waited   = Duration::from_secs(0);
+
+				'render: loop {
+					let now     = clock.now();
+					let elapsed = now - previous;
+
+					// Calculate accumulated lag.
+					previous  = now;
+					lag      += elapsed.as_nanosecs();
+
+					// Update the state by fixed steps, giving up on catching up after too
+					// many.
+					let mut steps = 0;
+					while lag >= step {
+						if options.catch_up.map_or(false, |max| steps >= max) {
+							let excess = lag - lag % step;
+
+							debug!("skipping {}ms after a stall", excess / 1_000_000);
+							saver.skipped(Duration::from_nanos(excess));
+
+							skipped += excess / step;
+							lag     -= excess;
+							break;
+						}
 
-						thread::sleep(Duration::from_millis(16));
-						continue;
+						saver.update_with(&Timing {
+							elapsed: options.step * updates as u32 + waited,
+							step:    options.step,
+							alpha:   0.0,
+							frame:   frames,
+						});
+
+						updates += 1;
+						steps   += 1;
+
+						if saver.state() == State::None {
+							break 'render;
+						}
+
+						lag -= step;
 					}
-					else {
-						skip = true;
+
+					// Handle requests.
+					while let Ok(event) = receiver.try_recv() {
+						match event {
+							Request::Resize { width, height } => {
+								resize!(width, height);
+							}
+
+							Request::Throttle(value) => {
+								saver.throttle(value);
+								throttle = value;
+								pace!();
+							}
+
+							Request::Blank(value) => {
+								saver.blank(value);
+								blank = value;
+							}
+
+							Request::Safety(level) => {
+								saver.safety(level)
+							}
+
+							Request::Pointer(pointer) => {
+								saver.pointer(pointer);
+							}
+
+							Request::Password(password) => {
+								saver.password(password);
+							}
+
+							Request::Lock => {
+								saver.lock();
+							}
+
+							Request::Stop => {
+								saver.stop();
+							}
+
+							Request::Capture(steps) => {
+								capture = Some(updates + steps);
+							}
+
+							Request::Ping(id) => {
+								sender.send(Response::Pong(id)).unwrap();
+							}
+
+							_ => ()
+						}
 					}
-				}
 
-				// Do not waste time rendering when the screen is blanked.
-				if !blank {
-					let mut target = display.draw();
-					target.clear_all((0.0, 0.0, 0.0, 1.0), 1.0, 0);
-					saver.render(&mut target, &texture);
-					target.finish().unwrap();
-				}
+					// The saver may be done without needing another update.
+					if saver.state() == State::None {
+						break 'render;
+					}
+
+					// Follow changes that didn't go through the locker.
+					for change in display.changes() {
+						match change {
+							Change::Resize { width, height } => {
+								resize!(width, height);
+							}
+
+							// The screen can't be captured again while the window covers it, so
+							// keep the old screenshot and only follow the size and layout.
+							Change::Screen => {
+								fatal!(screen.resize(&display));
+								saver.monitors(screen.monitors());
+								saver.resize(display.context());
+							}
+						}
+					}
 
-				// If the rendering was too fast, throttle it at 60 FPS.
-				let elapsed = now.elapsed().as_nanosecs();
-				if elapsed < 16_000_000 {
-					thread::sleep(Duration::new(0, 16_000_000 - elapsed as u32));
+					// Do not waste time rendering when the screen is blanked.
+					let mut busy = clock.now() - now;
+					if !blank {
+						let mut target = display.draw();
+						target.clear_all((0.0, 0.0, 0.0, 1.0), 1.0, 0);
+						saver.render_screen(&mut target, &screen, &Timing {
+							elapsed: options.step * updates as u32 + waited,
+							step:    options.step,
+							alpha:   lag as f32 / step as f32,
+							frame:   frames,
+						});
+
+						// Finishing waits for the buffer swap, which isn't on the saver.
+						busy = clock.now() - now;
+						fatal!(target.finish());
+						frames += 1;
+
+						if capture.map_or(false, |at| updates >= at) {
+							capture = None;
+							sender.send(Response::Frame(fatal!(display.capture()))).unwrap();
+						}
+					}
+
+					let frame   = options.frame(throttle);
+					let elapsed = clock.now() - now;
+
+					// Leave some slack so frames paced by the retrace don't count.
+					let budget = refresh.map_or(frame, |hz| cmp::max(frame, Duration::from_secs_f32(1.0 / hz)));
+					if !blank && elapsed > budget * 3 / 2 {
+						dropped += 1;
+					}
+
+					*stats.lock().unwrap() = Stats {
+						frames:     frames,
+						updates:    updates,
+						skipped:    skipped,
+						dropped:    dropped,
+						frame_time: busy,
+						throttled:  throttle,
+					};
+
+					// Only sleep while running, the begin and end animations have to
+					// keep going.
+					let redraw = if saver.state() == State::Running { saver.redraw() } else { Redraw::Always };
+					let timer  = match redraw {
+						Redraw::After(duration) if duration > frame =>
+							Some(clock.after(duration)),
+
+						Redraw::Idle =>
+							Some(never()),
+
+						_ =>
+							None,
+					};
+
+					if let Some(timer) = timer {
+						// Sleep until the deadline, the next request or an X event, whichever
+						// comes first; the connection can be readable without carrying an
+						// event, so go back to sleep if nothing turned up.
+						progress.set_idle(true);
+						let asleep = clock.now();
+
+						while !display.pending() {
+							let events     = display.events();
+							let mut select = Select::new();
+
+							select.recv(&receiver);
+							select.recv(&timer);
+							let evented = select.recv(&events);
+
+							if select.ready() != evented {
+								break;
+							}
+						}
+
+						// The saver asked to sleep, so the time doesn't count as lag to catch
+						// up on, it still counts as elapsed time though.
+						previous  = clock.now();
+						waited   += previous - asleep;
+
+						progress.set_idle(false);
+					}
+					// Swapping buffers already paces the loop with vsync, otherwise if the
+					// rendering was too fast cap it at the frame rate; without a known
+					// refresh rate vsync can't do that when throttled.
+					else if (!vsync || blank || (throttle && !paced)) && elapsed < frame {
+						clock.sleep(frame - elapsed);
+					}
+
+					progress.advance();
 				}
-			}
 
-			sender.send(Response::Stopped).unwrap();
-		});
+				stopped!();
+			}));
+
+			if let Err(payload) = result {
+				let message = util::panic_message(&*payload);
+
+				log::error!("renderer panicked: {}", message);
+				reporter.send(Response::Error(error::Error::Panic(message))).ok();
+			}
+		}).unwrap();
 
 		Renderer {
 			receiver: i_receiver,
 			sender:   i_sender,
+			thread:   Some(thread),
+			progress: i_progress,
+			stats:    i_stats,
 		}
 	}
 
+	/// The latest rendering statistics.
+	pub fn stats(&self) -> Stats {
+		*self.stats.lock().unwrap()
+	}
+
+	/// The progress of the render thread.
+	pub fn progress(&self) -> Arc<Progress> {
+		self.progress.clone()
+	}
+
+	/// Wait for the renderer to stop and release its resources, returns false if
+	/// it didn't within the timeout.
+	pub fn wait(&mut self, timeout: Duration) -> bool {
+		let deadline = Instant::now() + timeout;
+
+		loop {
+			match self.receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+				Ok(Response::Stopped) | Err(RecvTimeoutError::Disconnected) =>
+					break,
+
+				Ok(_) =>
+					continue,
+
+				Err(RecvTimeoutError::Timeout) =>
+					return false,
+			}
+		}
+
+		if let Some(thread) = self.thread.take() {
+			thread.join().ok();
+		}
+
+		true
+	}
+
 	pub fn resize(&self, width: u32, height: u32) -> Result<(), SendError<Request>> {
 		self.sender.send(Request::Resize { width: width, height: height })
 	}
@@ -269,6 +671,17 @@ impl Renderer {
 	pub fn stop(&self) -> Result<(), SendError<Request>> {
 		self.sender.send(Request::Stop)
 	}
+
+	/// Capture the frame rendered after the given number of update steps, it
+	/// will be sent back as `Response::Frame`.
+	pub fn capture(&self, steps: u64) -> Result<(), SendError<Request>> {
+		self.sender.send(Request::Capture(steps))
+	}
+
+	/// Check the render thread is alive, it will answer with `Response::Pong`.
+	pub fn ping(&self, id: u64) -> Result<(), SendError<Request>> {
+		self.sender.send(Request::Ping(id))
+	}
 }
 
 impl AsRef<Receiver<Response>> for Renderer {
@@ -282,3 +695,93 @@ impl AsRef<Sender<Request>> for Renderer {
 		&self.sender
 	}
 }
+
+#[cfg(all(test, feature = "headless"))]
+mod tests {
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::time::Duration;
+	use gl;
+	use crate::{Saver, State, Target};
+	use crate::clock::Virtual;
+	use super::*;
+
+	struct Counter {
+		updates: Arc<AtomicUsize>,
+		renders: Arc<AtomicUsize>,
+		state:   State,
+	}
+
+	impl Counter {
+		fn new() -> Self {
+			Counter {
+				updates: Arc::new(AtomicUsize::new(0)),
+				renders: Arc::new(AtomicUsize::new(0)),
+				state:   State::None,
+			}
+		}
+	}
+
+	impl Saver for Counter {
+		fn start(&mut self) {
+			self.state = State::Running;
+		}
+
+		fn stop(&mut self) {
+			self.state = State::None;
+		}
+
+		fn state(&self) -> State {
+			self.state
+		}
+
+		fn update(&mut self) {
+			self.updates.fetch_add(1, Ordering::SeqCst);
+		}
+
+		fn render<S: gl::Surface>(&self, _target: &mut S, _screen: &gl::texture::SrgbTexture2d) {
+			self.renders.fetch_add(1, Ordering::SeqCst);
+		}
+	}
+
+	#[test]
+	fn advance() {
+		let clock   = Arc::new(Virtual::new());
+		let saver   = Counter::new();
+		let updates = saver.updates.clone();
+		let renders = saver.renders.clone();
+		let counts  = || (updates.load(Ordering::SeqCst), renders.load(Ordering::SeqCst));
+
+		let mut renderer = Renderer::with_options(Target::Offscreen { width: 64, height: 64 }, Options {
+			clock:    clock.clone(),
+			vsync:    false,
+			catch_up: None,
+			.. Default::default()
+		}, saver);
+
+		let responses = AsRef::<Receiver<Response>>::as_ref(&renderer).clone();
+
+		assert!(matches!(responses.recv(), Ok(Response::Initialized)));
+		renderer.start().unwrap();
+		assert!(matches!(responses.recv(), Ok(Response::Started)));
+
+		// The first frame is rendered right away.
+		clock.park();
+		assert_eq!(counts(), (0, 1));
+
+		// One frame later there's one step worth of lag.
+		clock.step();
+		clock.park();
+		assert_eq!(counts(), (1, 2));
+
+		// Ten steps worth of lag are caught up on in one frame.
+		clock.advance(Duration::from_millis(150));
+		clock.park();
+		assert_eq!(counts(), (11, 3));
+
+		// The stop is acted upon as soon as it's seen on the next frame.
+		renderer.stop().unwrap();
+		clock.step();
+		assert!(renderer.wait(Duration::from_secs(1)));
+	}
+}
@@ -13,11 +13,12 @@
 //  0. You just DO WHAT THE FUCK YOU WANT TO.
 
 use std::rc::Rc;
+use std::time::Duration;
 
 use gl;
 use json;
 
-use crate::{State, Safety, Password, Pointer};
+use crate::{State, Safety, Password, Pointer, Timing, Redraw, Monitor, Screen};
 
 #[allow(unused_variables)]
 pub trait Saver {
@@ -30,6 +31,10 @@ pub trait Saver {
 	/// Resize the viewport.
 	fn resize(&mut self, context: Rc<gl::backend::Context>) { }
 
+	/// The monitors showing the screen, given before initialization and again
+	/// on every resize.
+	fn monitors(&mut self, monitors: &[Monitor]) { }
+
 	/// Whether to try and reduce power usage or not.
 	fn throttle(&mut self, value: bool) { }
 
@@ -59,9 +64,34 @@ pub trait Saver {
 	/// Return the current saver state.
 	fn state(&self) -> State;
 
-	/// Called every 15 milliseconds.
+	/// Called every fixed step, 15 milliseconds by default.
 	fn update(&mut self) { }
 
+	/// Called every fixed step, with timing information.
+	fn update_with(&mut self, timing: &Timing) {
+		self.update();
+	}
+
+	/// Updates worth the given duration have been skipped after a stall, useful
+	/// to resynchronize animations with the wall clock.
+	fn skipped(&mut self, duration: Duration) { }
+
+	/// When the saver needs to be drawn again, asked after every frame while
+	/// running; while beginning or ending it's drawn on every frame.
+	fn redraw(&self) -> Redraw {
+		Redraw::Always
+	}
+
 	/// Render the saver.
-	fn render<S: gl::Surface>(&self, target: &mut S, screen: &gl::texture::SrgbTexture2d);
+	fn render<S: gl::Surface>(&self, target: &mut S, screen: &gl::texture::SrgbTexture2d) { }
+
+	/// Render the saver, with timing information.
+	fn render_with<S: gl::Surface>(&self, target: &mut S, screen: &gl::texture::SrgbTexture2d, timing: &Timing) {
+		self.render(target, screen);
+	}
+
+	/// Render the saver, with the screen split by monitor.
+	fn render_screen<S: gl::Surface>(&self, target: &mut S, screen: &Screen, timing: &Timing) {
+		self.render_with(target, screen.texture(), timing);
+	}
 }
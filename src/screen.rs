@@ -0,0 +1,167 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::rc::Rc;
+use std::borrow::Cow;
+
+use gl::{self, Surface};
+use picto;
+
+use crate::{error, Display, Monitor};
+
+/// The screen as it was before the saver started.
+///
+/// The screenshot is never taken again, once the saver covers the screen there
+/// is nothing else to capture; when the screen size changes the texture
+/// follows it, with whatever wasn't captured left black.
+pub struct Screen {
+	texture:  gl::texture::SrgbTexture2d,
+	deep:     gl::texture::Texture2d,
+	monitors: Vec<Monitor>,
+}
+
+/// Texture coordinates of a region of the screen, from 0 to 1 with the origin
+/// at the bottom left like OpenGL.
+#[derive(PartialEq, Copy, Clone, Default, Debug)]
+pub struct Uv {
+	pub left:   f32,
+	pub bottom: f32,
+	pub right:  f32,
+	pub top:    f32,
+}
+
+impl Screen {
+	/// Take a screenshot of the display and put it in the textures.
+	pub fn capture(display: &Display) -> error::Result<Screen> {
+		let context         = display.context();
+		let image           = display.screenshot::<picto::color::Rgb, u16>();
+		let (width, height) = image.dimensions();
+
+		let texture = {
+			let image = image.convert::<picto::color::Rgba, u8>();
+
+			gl::texture::SrgbTexture2d::new(&context,
+				gl::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), (width, height)))?
+		};
+
+		let deep = {
+			let rows = image.into_raw();
+			let data = rows.chunks(width as usize * 3).rev().flatten().cloned().collect::<Vec<u16>>();
+
+			gl::texture::Texture2d::with_format(&context,
+				gl::texture::RawImage2d {
+					data:   Cow::Owned(data),
+					width:  width,
+					height: height,
+					format: gl::texture::ClientFormat::U16U16U16,
+				},
+				gl::texture::UncompressedFloatFormat::U16U16U16U16,
+				gl::texture::MipmapsOption::NoMipmap)?
+		};
+
+		Ok(Screen {
+			texture:  texture,
+			deep:     deep,
+			monitors: display.monitors(),
+		})
+	}
+
+	/// The whole screen, with 8 bits per channel since there is no deeper sRGB
+	/// format.
+	pub fn texture(&self) -> &gl::texture::SrgbTexture2d {
+		&self.texture
+	}
+
+	/// The whole screen with 16 bits per channel, so deep color screens keep
+	/// their precision; the values are the sRGB encoded ones, sampling does
+	/// not decode them.
+	pub fn deep_texture(&self) -> &gl::texture::Texture2d {
+		&self.deep
+	}
+
+	/// The monitors showing the screen.
+	pub fn monitors(&self) -> &[Monitor] {
+		&self.monitors
+	}
+
+	/// Change the monitors, the texture is left alone.
+	pub fn set_monitors(&mut self, monitors: Vec<Monitor>) {
+		self.monitors = monitors;
+	}
+
+	/// Follow a change of the screen size or layout, the captured part stays
+	/// at the top left.
+	pub fn resize(&mut self, display: &Display) -> error::Result<()> {
+		let context = display.context();
+		let from    = (self.texture.width(), self.texture.height());
+		let to      = display.screen_dimensions();
+
+		if from != to {
+			let texture = gl::texture::SrgbTexture2d::empty(&context, to.0, to.1)?;
+			copy(&context, &self.texture, from, &texture, to);
+
+			let deep = gl::texture::Texture2d::empty_with_format(&context,
+				gl::texture::UncompressedFloatFormat::U16U16U16U16,
+				gl::texture::MipmapsOption::NoMipmap, to.0, to.1)?;
+			copy(&context, &self.deep, from, &deep, to);
+
+			self.texture = texture;
+			self.deep    = deep;
+		}
+
+		self.monitors = display.monitors();
+
+		Ok(())
+	}
+
+	/// The part of the texture shown on the given monitor.
+	pub fn uv(&self, monitor: &Monitor) -> Uv {
+		let width  = self.texture.width() as f32;
+		let height = self.texture.height() as f32;
+
+		Uv {
+			left:   monitor.x as f32 / width,
+			right:  (monitor.x as f32 + monitor.width as f32) / width,
+			bottom: 1.0 - (monitor.y as f32 + monitor.height as f32) / height,
+			top:    1.0 - monitor.y as f32 / height,
+		}
+	}
+
+	/// The monitors along with the part of the texture they show.
+	pub fn outputs<'a>(&'a self) -> impl Iterator<Item = (&'a Monitor, Uv)> + 'a {
+		self.monitors.iter().map(move |m| (m, self.uv(m)))
+	}
+}
+
+/// Copy the top left of a texture to the top left of another, clearing the
+/// rest to black.
+fn copy<'a, T>(context: &Rc<gl::backend::Context>, from: &'a T, from_size: (u32, u32), to: &'a T, to_size: (u32, u32))
+	where &'a T: gl::framebuffer::ToColorAttachment<'a>
+{
+	// Attaching a whole texture we just created can't fail.
+	let     source = gl::framebuffer::SimpleFrameBuffer::new(context, from).unwrap();
+	let mut target = gl::framebuffer::SimpleFrameBuffer::new(context, to).unwrap();
+
+	let width  = from_size.0.min(to_size.0);
+	let height = from_size.1.min(to_size.1);
+
+	target.clear_color(0.0, 0.0, 0.0, 1.0);
+
+	// The textures are upside down, so the top left is at the far end.
+	source.blit_color(
+		&gl::Rect { left: 0, bottom: from_size.1 - height, width: width, height: height },
+		&target,
+		&gl::BlitTarget { left: 0, bottom: to_size.1 - height, width: width as i32, height: height as i32 },
+		gl::uniforms::MagnifySamplerFilter::Nearest);
+}
@@ -0,0 +1,373 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::ptr;
+use std::mem;
+use std::slice;
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use libc::{c_char, c_int, c_uint, c_ulong, c_void};
+use x11::{xlib, xshm};
+use picto;
+use picto::color::Rgb;
+
+use crate::library;
+
+/// A screenshot in RGB, with 16 bits per channel so deep color visuals keep
+/// their precision.
+pub type Image = picto::Buffer<Rgb, u16, Vec<u16>>;
+
+type QueryExtension = unsafe extern "C" fn(*mut xlib::Display) -> xlib::Bool;
+type CreateImage    = unsafe extern "C" fn(*mut xlib::Display, *mut xlib::Visual, c_uint, c_int, *mut c_char, *mut xshm::XShmSegmentInfo, c_uint, c_uint) -> *mut xlib::XImage;
+type Attach         = unsafe extern "C" fn(*mut xlib::Display, *mut xshm::XShmSegmentInfo) -> xlib::Bool;
+type Detach         = unsafe extern "C" fn(*mut xlib::Display, *mut xshm::XShmSegmentInfo) -> xlib::Bool;
+type GetImage       = unsafe extern "C" fn(*mut xlib::Display, xlib::Drawable, *mut xlib::XImage, c_int, c_int, c_ulong) -> xlib::Bool;
+
+/// MIT-SHM from libXext, loaded at runtime like XRandR since a plain
+/// `XGetImage` does the job without it.
+struct Shm {
+	// Kept loaded for as long as the functions are around.
+	_library: library::Library,
+
+	query_extension: QueryExtension,
+	create_image:    CreateImage,
+	attach:          Attach,
+	detach:          Detach,
+	get_image:       GetImage,
+}
+
+impl Shm {
+	/// Load the library, if it's installed.
+	fn open() -> Option<Shm> {
+		let library = library::Library::open(CStr::from_bytes_with_nul(b"libXext.so.6\0").unwrap())?;
+
+		macro_rules! symbol {
+			($name:expr, $kind:ty) => (unsafe {
+				mem::transmute::<*mut c_void, $kind>(
+					library.symbol(CStr::from_bytes_with_nul(concat!($name, "\0").as_bytes()).unwrap())?)
+			});
+		}
+
+		Some(Shm {
+			query_extension: symbol!("XShmQueryExtension", QueryExtension),
+			create_image:    symbol!("XShmCreateImage", CreateImage),
+			attach:          symbol!("XShmAttach", Attach),
+			detach:          symbol!("XShmDetach", Detach),
+			get_image:       symbol!("XShmGetImage", GetImage),
+
+			_library: library,
+		})
+	}
+}
+
+/// Take a screenshot of the given window, through shared memory if possible.
+pub fn capture(display: *mut xlib::Display, window: xlib::Window, width: u32, height: u32) -> Image {
+	unsafe {
+		shared(display, window, width, height)
+			.unwrap_or_else(|| plain(display, window, width, height))
+	}
+}
+
+/// Take a screenshot with the MIT-SHM extension, so the pixels don't go through
+/// the socket.
+unsafe fn shared(display: *mut xlib::Display, window: xlib::Window, width: u32, height: u32) -> Option<Image> {
+	let shm = Shm::open()?;
+
+	if (shm.query_extension)(display) == xlib::False {
+		return None;
+	}
+
+	let mut attributes = mem::zeroed::<xlib::XWindowAttributes>();
+	if xlib::XGetWindowAttributes(display, window, &mut attributes) == 0 {
+		return None;
+	}
+
+	let mut info   = mem::zeroed::<xshm::XShmSegmentInfo>();
+	let     ximage = (shm.create_image)(display, attributes.visual, attributes.depth as u32,
+		xlib::ZPixmap, ptr::null_mut(), &mut info, width, height).as_mut()?;
+
+	info.shmid = libc::shmget(libc::IPC_PRIVATE,
+		(ximage.bytes_per_line * ximage.height) as usize, libc::IPC_CREAT | 0o600);
+
+	if info.shmid < 0 {
+		xlib::XDestroyImage(ximage);
+		return None;
+	}
+
+	info.shmaddr  = libc::shmat(info.shmid, ptr::null(), 0) as *mut _;
+	info.readOnly = xlib::False;
+	ximage.data   = info.shmaddr;
+
+	// The segment goes away once both sides detach, even if we crash.
+	let attached = info.shmaddr as isize != -1 && attach(&shm, display, &mut info);
+	libc::shmctl(info.shmid, libc::IPC_RMID, ptr::null_mut());
+
+	let image = if attached {
+		let image = if (shm.get_image)(display, window, ximage, 0, 0, xlib::XAllPlanes()) != 0 {
+			Some(convert(ximage))
+		}
+		else {
+			None
+		};
+
+		(shm.detach)(display, &mut info);
+		xlib::XSync(display, xlib::False);
+
+		image
+	}
+	else {
+		None
+	};
+
+	if info.shmaddr as isize != -1 {
+		libc::shmdt(info.shmaddr as *const c_void);
+	}
+
+	// The data is not owned by Xlib.
+	ximage.data = ptr::null_mut();
+	xlib::XDestroyImage(ximage);
+
+	image
+}
+
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" fn failed(_display: *mut xlib::Display, _event: *mut xlib::XErrorEvent) -> c_int {
+	FAILED.store(true, Ordering::SeqCst);
+	0
+}
+
+/// Attach the segment to the server, which fails on remote displays.
+unsafe fn attach(shm: &Shm, display: *mut xlib::Display, info: &mut xshm::XShmSegmentInfo) -> bool {
+	xlib::XSync(display, xlib::False);
+	FAILED.store(false, Ordering::SeqCst);
+
+	let previous = xlib::XSetErrorHandler(Some(failed));
+	let attached = (shm.attach)(display, info) != 0;
+
+	xlib::XSync(display, xlib::False);
+	xlib::XSetErrorHandler(previous);
+
+	attached && !FAILED.load(Ordering::SeqCst)
+}
+
+/// Take a screenshot with a plain `XGetImage`.
+unsafe fn plain(display: *mut xlib::Display, window: xlib::Window, width: u32, height: u32) -> Image {
+	let ximage = xlib::XGetImage(display, window,
+		0, 0, width, height, xlib::XAllPlanes(), xlib::ZPixmap)
+			.as_mut().unwrap();
+
+	let image = convert(ximage);
+	xlib::XDestroyImage(ximage);
+
+	image
+}
+
+/// Where a color channel lives within a pixel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Channel {
+	shift: u32,
+	width: u32,
+}
+
+impl Channel {
+	/// Derive the position from the visual mask, which is always contiguous.
+	fn new(mask: u64) -> Self {
+		if mask == 0 {
+			return Channel { shift: 0, width: 0 };
+		}
+
+		let shift = mask.trailing_zeros();
+		let width = (mask >> shift).trailing_ones();
+
+		Channel { shift, width }
+	}
+
+	/// Extract the channel from the pixel and scale it to 16 bits.
+	fn get(&self, pixel: u64) -> u16 {
+		if self.width == 0 {
+			return 0;
+		}
+
+		let value = (pixel >> self.shift) & ((1 << self.width) - 1);
+
+		if self.width >= 16 {
+			(value >> (self.width - 16)) as u16
+		}
+		else {
+			(value * 0xffff / ((1 << self.width) - 1)) as u16
+		}
+	}
+}
+
+/// The layout of the color channels within a pixel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Format {
+	red:   Channel,
+	green: Channel,
+	blue:  Channel,
+}
+
+impl Format {
+	fn new(ximage: &xlib::XImage) -> Self {
+		Format {
+			red:   Channel::new(ximage.red_mask),
+			green: Channel::new(ximage.green_mask),
+			blue:  Channel::new(ximage.blue_mask),
+		}
+	}
+
+	fn push(&self, pixels: &mut Vec<u16>, pixel: u64) {
+		pixels.push(self.red.get(pixel));
+		pixels.push(self.green.get(pixel));
+		pixels.push(self.blue.get(pixel));
+	}
+}
+
+/// Convert the contents of a `ZPixmap` image to RGB.
+unsafe fn convert(ximage: &mut xlib::XImage) -> Image {
+	let width  = ximage.width as u32;
+	let height = ximage.height as u32;
+	let format = Format::new(ximage);
+	let bytes  = match ximage.bits_per_pixel {
+		8 | 16 | 24 | 32 =>
+			(ximage.bits_per_pixel / 8) as usize,
+
+		// Odd layouts are rare enough to go through Xlib.
+		_ =>
+			return slow(ximage, format),
+	};
+
+	let msb   = ximage.byte_order == xlib::MSBFirst;
+	let data  = slice::from_raw_parts(ximage.data as *const u8, (ximage.bytes_per_line * ximage.height) as usize);
+	let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+
+	for row in data.chunks(ximage.bytes_per_line as usize) {
+		for pixel in row[.. width as usize * bytes].chunks(bytes) {
+			let pixel = if msb {
+				pixel.iter().fold(0, |acc, &b| (acc << 8) | b as u64)
+			}
+			else {
+				pixel.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64)
+			};
+
+			format.push(&mut pixels, pixel);
+		}
+	}
+
+	Image::from_raw(width, height, pixels).unwrap()
+}
+
+/// Convert the image one pixel at a time through Xlib.
+unsafe fn slow(ximage: &mut xlib::XImage, format: Format) -> Image {
+	let width  = ximage.width as u32;
+	let height = ximage.height as u32;
+	let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+
+	for y in 0 .. height {
+		for x in 0 .. width {
+			format.push(&mut pixels, xlib::XGetPixel(ximage, x as c_int, y as c_int) as u64);
+		}
+	}
+
+	Image::from_raw(width, height, pixels).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::mem;
+	use x11::xlib;
+	use super::*;
+
+	/// Build a fake image over the given data.
+	fn ximage(data: &mut Vec<u8>, width: i32, height: i32, bits: i32, stride: i32, order: i32, masks: (u64, u64, u64)) -> xlib::XImage {
+		let mut ximage = unsafe { mem::zeroed::<xlib::XImage>() };
+
+		ximage.width          = width;
+		ximage.height         = height;
+		ximage.format         = xlib::ZPixmap;
+		ximage.data           = data.as_mut_ptr() as *mut _;
+		ximage.byte_order     = order;
+		ximage.bits_per_pixel = bits;
+		ximage.bytes_per_line = stride;
+		ximage.red_mask       = masks.0 as _;
+		ximage.green_mask     = masks.1 as _;
+		ximage.blue_mask      = masks.2 as _;
+
+		ximage
+	}
+
+	#[test]
+	fn channel() {
+		assert_eq!(Channel::new(0xff0000), Channel { shift: 16, width: 8 });
+		assert_eq!(Channel::new(0x7e0), Channel { shift: 5, width: 6 });
+		assert_eq!(Channel::new(0x3ff00000), Channel { shift: 20, width: 10 });
+		assert_eq!(Channel::new(0), Channel { shift: 0, width: 0 });
+	}
+
+	#[test]
+	fn rgb888() {
+		let mut data   = vec![0x30, 0x20, 0x10, 0x00, 0xff, 0x00, 0x00, 0x00];
+		let mut ximage = ximage(&mut data, 2, 1, 32, 8, xlib::LSBFirst, (0xff0000, 0xff00, 0xff));
+		let     image  = unsafe { convert(&mut ximage) };
+
+		assert_eq!(&image[..], &[0x1010, 0x2020, 0x3030, 0, 0, 0xffff]);
+	}
+
+	#[test]
+	fn bgr888() {
+		let mut data   = vec![0x30, 0x20, 0x10, 0x00];
+		let mut ximage = ximage(&mut data, 1, 1, 32, 4, xlib::LSBFirst, (0xff, 0xff00, 0xff0000));
+		let     image  = unsafe { convert(&mut ximage) };
+
+		assert_eq!(&image[..], &[0x3030, 0x2020, 0x1010]);
+	}
+
+	#[test]
+	fn rgb565() {
+		let mut data   = vec![0x00, 0xf8, 0xe0, 0x07, 0x1f, 0x00, 0x10, 0x84];
+		let mut ximage = ximage(&mut data, 4, 1, 16, 8, xlib::LSBFirst, (0xf800, 0x7e0, 0x1f));
+		let     image  = unsafe { convert(&mut ximage) };
+
+		assert_eq!(&image[..], &[
+			0xffff, 0, 0,
+			0, 0xffff, 0,
+			0, 0, 0xffff,
+			0x8420, 0x8207, 0x8420,
+		]);
+	}
+
+	#[test]
+	fn rgb101010() {
+		// Red at full intensity, green at the lowest non-zero level and blue at
+		// half, the green would be lost at 8 bits.
+		let pixel: u32 = (0x3ff << 20) | (0x001 << 10) | 0x200;
+		let mut data   = pixel.to_le_bytes().to_vec();
+		let mut ximage = ximage(&mut data, 1, 1, 32, 4, xlib::LSBFirst, (0x3ff00000, 0xffc00, 0x3ff));
+		let     image  = unsafe { convert(&mut ximage) };
+
+		assert_eq!(&image[..], &[0xffff, 0x0040, 0x801f]);
+	}
+
+	#[test]
+	fn msb_with_padding() {
+		// Two rows of one 24-bit pixel, padded to four bytes.
+		let mut data   = vec![0x10, 0x20, 0x30, 0xaa, 0xff, 0xff, 0xff, 0xaa];
+		let mut ximage = ximage(&mut data, 1, 2, 24, 4, xlib::MSBFirst, (0xff0000, 0xff00, 0xff));
+		let     image  = unsafe { convert(&mut ximage) };
+
+		assert_eq!(&image[..], &[0x1010, 0x2020, 0x3030, 0xffff, 0xffff, 0xffff]);
+	}
+}
@@ -0,0 +1,39 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::time::Duration;
+
+/// Performance statistics collected by the renderer.
+#[derive(PartialEq, Eq, Copy, Clone, Default, Debug)]
+pub struct Stats {
+	/// The number of rendered frames.
+	pub frames: u64,
+
+	/// The number of update steps.
+	pub updates: u64,
+
+	/// The number of update steps given up on after a stall.
+	pub skipped: u64,
+
+	/// The number of frames that took over one and a half times longer than the
+	/// frame rate, or the refresh rate, allows.
+	pub dropped: u64,
+
+	/// How long the last frame took to update and render, not counting the
+	/// wait for the buffer swap.
+	pub frame_time: Duration,
+
+	/// Whether the rendering is throttled.
+	pub throttled: bool,
+}
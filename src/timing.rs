@@ -0,0 +1,34 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::time::Duration;
+
+/// Timing information for an update step or a rendered frame.
+#[derive(PartialEq, Copy, Clone, Default, Debug)]
+pub struct Timing {
+	/// The simulated time since the saver started, advances by `step` on every
+	/// update and by the time spent sleeping after `Redraw::After` or
+	/// `Redraw::Idle`.
+	pub elapsed: Duration,
+
+	/// The duration of a fixed update step.
+	pub step: Duration,
+
+	/// How far into the next step the frame is, from 0 to 1, useful to
+	/// interpolate between the last two updates; always 0 on updates.
+	pub alpha: f32,
+
+	/// The index of the current frame.
+	pub frame: u64,
+}
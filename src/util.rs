@@ -28,3 +28,17 @@ impl DurationExt for Duration {
 		self.as_secs() * 1_000_000_000 + self.subsec_nanos() as u64
 	}
 }
+
+/// Extract the message from a panic payload.
+#[cfg(feature = "renderer")]
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+	if let Some(message) = payload.downcast_ref::<&str>() {
+		(*message).into()
+	}
+	else if let Some(message) = payload.downcast_ref::<String>() {
+		message.clone()
+	}
+	else {
+		"unknown panic".into()
+	}
+}
@@ -0,0 +1,117 @@
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//                    Version 2, December 2004
+//
+// Copyleft (ↄ) meh. <meh@schizofreni.co> | http://meh.schizofreni.co
+//
+// Everyone is permitted to copy and distribute verbatim or modified
+// copies of this license document, and changing it is allowed as long
+// as the name is changed.
+//
+//            DO WHAT THE FUCK YOU WANT TO PUBLIC LICENSE
+//   TERMS AND CONDITIONS FOR COPYING, DISTRIBUTION AND MODIFICATION
+//
+//  0. You just DO WHAT THE FUCK YOU WANT TO.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{unbounded, Receiver};
+use json::JsonValue;
+
+use crate::renderer::Progress;
+
+/// Watchdog settings.
+#[derive(Copy, Clone, Debug)]
+pub struct Options {
+	/// How long the renderer can go without completing a frame, `None` to never
+	/// give up.
+	pub timeout: Option<Duration>,
+
+	/// How often to check on the renderer and send a heartbeat.
+	pub interval: Duration,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Options {
+			timeout:  Some(Duration::from_secs(10)),
+			interval: Duration::from_secs(1),
+		}
+	}
+}
+
+impl Options {
+	/// Read the settings from the `watchdog` section of the saver
+	/// configuration, falling back to the defaults.
+	pub fn from_config(config: &JsonValue) -> Self {
+		let mut options = Options::default();
+		let     config  = &config["watchdog"];
+
+		// Zero means never give up.
+		if let Some(value) = config["timeout"].as_u64() {
+			options.timeout = Some(value).filter(|&v| v > 0).map(Duration::from_millis);
+		}
+
+		if let Some(value) = config["interval"].as_u64().filter(|&v| v > 0) {
+			options.interval = Duration::from_millis(value);
+		}
+
+		options
+	}
+}
+
+#[derive(Debug)]
+pub enum Event {
+	/// The renderer is alive and has completed the given number of frames.
+	Heartbeat(u64),
+
+	/// The renderer made no progress for the given duration.
+	Hung(Duration),
+}
+
+/// Keeps an eye on the render thread from a separate thread.
+pub struct Watchdog {
+	receiver: Receiver<Event>,
+}
+
+impl Watchdog {
+	/// Start watching the given progress.
+	pub fn spawn(progress: Arc<Progress>, options: Options) -> Watchdog {
+		let (sender, receiver) = unbounded();
+
+		thread::spawn(move || {
+			let mut frame = progress.frame();
+			let mut since = Instant::now();
+
+			loop {
+				thread::sleep(options.interval);
+
+				// Waiting for requests doesn't count as being stuck.
+				let current = progress.frame();
+				if current != frame || progress.idle() {
+					frame = current;
+					since = Instant::now();
+				}
+				else if options.timeout.map_or(false, |timeout| since.elapsed() >= timeout) {
+					sender.send(Event::Hung(since.elapsed())).ok();
+					break;
+				}
+
+				if sender.send(Event::Heartbeat(current)).is_err() {
+					break;
+				}
+			}
+		});
+
+		Watchdog {
+			receiver: receiver,
+		}
+	}
+}
+
+impl AsRef<Receiver<Event>> for Watchdog {
+	fn as_ref(&self) -> &Receiver<Event> {
+		&self.receiver
+	}
+}